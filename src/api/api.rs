@@ -1,13 +1,43 @@
-use std::net::Ipv4Addr;
+use std::{net::Ipv4Addr, sync::Arc};
 
-use rocket::{get, serde::json::Json, Build, Config, Rocket, Route};
+use rocket::{
+    get, http::Status, response::status, serde::json::Json, Build, Config, Rocket, Route, State,
+};
 use serde::Serialize;
 
+use crate::{
+    config::Settings,
+    error::Error,
+    services::{
+        bakery_queue::QueueStats,
+        discovery::{discover, FeedCandidate},
+    },
+    utils::{Clients, DBBag},
+};
+
 #[derive(Clone, Debug, Serialize)]
 pub struct ServiceHealth {
     health: String,
 }
 
+/// bakery_queue returns the depth of the bakery dispatch queue and its wait times
+#[get("/bakery/queue")]
+pub fn bakery_queue(clients: &State<Arc<Clients>>) -> Option<Json<QueueStats>> {
+    clients.bakery.queue_stats().map(Json)
+}
+
+/// discover lists the feeds found for the page at `url`
+#[get("/discover?<url>")]
+pub async fn discover_feeds(
+    url: &str,
+    clients: &State<Arc<Clients>>,
+) -> Result<Json<Vec<FeedCandidate>>, status::Custom<String>> {
+    discover(&clients.http, url)
+        .await
+        .map(Json)
+        .map_err(|err| status::Custom(Status::BadGateway, Error::from(err).0))
+}
+
 #[get("/healthcheck")]
 pub fn healthcheck() -> Json<ServiceHealth> {
     Json(ServiceHealth {
@@ -15,7 +45,13 @@ pub fn healthcheck() -> Json<ServiceHealth> {
     })
 }
 
-pub async fn lezgong(routes: Vec<Route>, port: u16) -> Rocket<Build> {
+pub async fn lezgong(
+    routes: Vec<Route>,
+    port: u16,
+    db_bag: Arc<DBBag>,
+    settings: Arc<Settings>,
+    clients: Arc<Clients>,
+) -> Rocket<Build> {
     rocket::build()
         .configure(Config {
             port,
@@ -26,5 +62,8 @@ pub async fn lezgong(routes: Vec<Route>, port: u16) -> Rocket<Build> {
             log_level: rocket::config::LogLevel::Normal,
             ..Config::default()
         })
+        .manage(db_bag)
+        .manage(settings)
+        .manage(clients)
         .mount("/patishie", routes)
 }
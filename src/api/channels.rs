@@ -0,0 +1,122 @@
+use std::sync::Arc;
+
+use mongodb::bson::doc;
+use rocket::{get, http::Status, post, response::status, serde::json::Json, State};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    db::model::{CollectionModel, SortOrder},
+    entities::{
+        channel::{new_with_seq_db, Channel},
+        filter_rule::FilterRule,
+        source_type::SourceType,
+    },
+    services::{
+        credentials::channel_credential,
+        discovery::resolve_source,
+        filter::{compile_rules, preview, PreviewItem},
+    },
+    task::fetch_articles,
+    utils::{Clients, DBBag},
+};
+
+/// channels lists every channel of a `tenant`, without the values of their headers
+#[get("/<tenant>/channels")]
+pub async fn channels(tenant: &str, db_bag: &State<Arc<DBBag>>) -> Json<Vec<Channel>> {
+    Json(
+        db_bag
+            .channels_coll
+            .find(doc! {"tenant": tenant}, ("id", SortOrder::ASC), None)
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .map(Channel::redacted)
+            .collect(),
+    )
+}
+
+/// NewChannel is the body of a channel creation.
+/// Without a `source_type`, the feed of `url` is discovered, falling back to bakery when it has none.
+#[derive(Debug, Deserialize)]
+pub struct NewChannel {
+    pub name: Option<String>,
+    pub url: String,
+    pub source_type: Option<SourceType>,
+}
+
+/// create_channel adds a channel to a `tenant`, named after its feed unless given a `name`
+#[post("/<tenant>/channels", format = "json", data = "<new_channel>")]
+pub async fn create_channel(
+    tenant: &str,
+    new_channel: Json<NewChannel>,
+    db_bag: &State<Arc<DBBag>>,
+    clients: &State<Arc<Clients>>,
+) -> Result<Json<Channel>, status::Custom<String>> {
+    let NewChannel {
+        name,
+        url,
+        source_type,
+    } = new_channel.into_inner();
+    let (url, source_type, title) = match source_type {
+        Some(source_type) => (url, source_type, None),
+        None => resolve_source(&clients.http, &url)
+            .await
+            .map_err(|err| status::Custom(Status::BadGateway, err.0))?,
+    };
+    let known = db_bag
+        .channels_coll
+        .find(
+            doc! {"tenant": tenant, "$or": [{"url": &url}, {"url_history.url": &url}]},
+            None,
+            1,
+        )
+        .await
+        .unwrap_or_default();
+    if let Some(channel) = known.first() {
+        return Err(status::Custom(
+            Status::Conflict,
+            format!("channel {} already fetches {}", channel.id, url),
+        ));
+    }
+    let name = name.or(title).unwrap_or_else(|| url.clone());
+    new_with_seq_db(tenant, &name, &url, source_type, &db_bag.channels_coll)
+        .await
+        .map(Json)
+        .map_err(|err| status::Custom(Status::InternalServerError, err.0))
+}
+
+/// preview_filters fetches a channel's source without storing anything, and tells for each item
+/// whether it would be kept by the filter `rules` given in the body, or by the channel's ones.
+/// Sources which gave nothing but warnings, e.g. unreachable or broken feeds, are a bad gateway.
+#[post("/<tenant>/channels/<id>/preview", data = "<rules>")]
+pub async fn preview_filters(
+    tenant: &str,
+    id: i32,
+    rules: Option<Json<Vec<FilterRule>>>,
+    db_bag: &State<Arc<DBBag>>,
+    clients: &State<Arc<Clients>>,
+) -> Result<Json<Vec<PreviewItem>>, status::Custom<String>> {
+    let channel = db_bag
+        .channels_coll
+        .find(doc! {"tenant": tenant, "id": id}, None, 1)
+        .await
+        .unwrap_or_default()
+        .pop()
+        .ok_or_else(|| status::Custom(Status::NotFound, format!("no channel {}", id)))?;
+    let rules = match rules {
+        Some(Json(rules)) => rules,
+        None => channel.get_filters().to_vec(),
+    };
+    let compiled = compile_rules(&rules).map_err(|err| status::Custom(Status::BadRequest, err.0))?;
+    let credential = channel_credential(db_bag, &channel)
+        .await
+        .map_err(|err| status::Custom(Status::InternalServerError, err.0))?;
+    let fetched = fetch_articles(clients, &channel, credential.as_ref(), Uuid::new_v4())
+        .await
+        .map_err(|err| status::Custom(Status::BadGateway, err.0))?;
+    if let Some(failure) = fetched.failure() {
+        return Err(status::Custom(Status::BadGateway, failure));
+    }
+    Ok(Json(preview(fetched.articles, &compiled)))
+}
@@ -0,0 +1,75 @@
+use std::sync::Arc;
+
+use mongodb::bson::doc;
+use rocket::{get, http::Status, response::status, serde::json::Json, State};
+
+use crate::{
+    config::Settings,
+    db::model::{CollectionModel, SortOrder},
+    entities::potential_articles::PotentialArticle,
+    services::language::normalize_language,
+    utils::DBBag,
+};
+
+/// items returns the latest items of a `tenant`, optionally restricted to a `channel_id`.
+/// `after` (ms) only keeps items created after this date.
+/// `cluster` lists the near-duplicates of a cluster, while `collapse` only keeps
+/// the canonical item of each cluster. `podcast` only keeps podcast episodes,
+/// `tag` the items of a canonical tag, and `language` those of a language (e.g. "ja"),
+/// a language which is not a language tag being a bad request.
+/// `limit` is brought within 1 and `max_items_limit`.
+#[get("/<tenant>/items?<limit>&<channel_id>&<after>&<cluster>&<collapse>&<podcast>&<tag>&<language>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn items(
+    tenant: &str,
+    limit: Option<i64>,
+    channel_id: Option<i32>,
+    after: Option<i64>,
+    cluster: Option<&str>,
+    collapse: Option<bool>,
+    podcast: Option<bool>,
+    tag: Option<&str>,
+    language: Option<&str>,
+    db_bag: &State<Arc<DBBag>>,
+    settings: &State<Arc<Settings>>,
+) -> Result<Json<Vec<PotentialArticle>>, status::Custom<String>> {
+    let mut filter = doc! {"tenant": tenant};
+    if let Some(id) = channel_id {
+        filter.insert("channel_id", id);
+    }
+    if let Some(cluster_id) = cluster {
+        filter.insert("cluster_id", cluster_id);
+    }
+    if let Some(tag) = tag {
+        filter.insert("tags", tag);
+    }
+    if let Some(declared) = language {
+        let language = normalize_language(declared)
+            .ok_or_else(|| status::Custom(Status::BadRequest, format!("unknown language {}", declared)))?;
+        filter.insert("language", language);
+    }
+    if podcast.unwrap_or(false) {
+        filter.insert("podcast", doc! {"$type": "object"});
+    }
+    if collapse.unwrap_or(false) {
+        filter.insert(
+            "$expr",
+            doc! {"$eq": [{"$ifNull": ["$cluster_id", "$canonical_link"]}, "$canonical_link"]},
+        );
+    }
+    Ok(Json(
+        db_bag
+            .items_coll
+            .find_latests(
+                "create_date",
+                after,
+                limit
+                    .unwrap_or(settings.default_item_per_feed)
+                    .clamp(1, settings.max_items_limit.max(1)),
+                SortOrder::DESC,
+                filter,
+            )
+            .await
+            .unwrap_or_default(),
+    ))
+}
@@ -1 +1,8 @@
+#[allow(clippy::module_inception)]
 pub mod api;
+pub mod channels;
+pub mod items;
+pub mod search;
+pub mod secrets;
+pub mod tags;
+pub mod websub;
@@ -0,0 +1,47 @@
+use std::sync::Arc;
+
+use rocket::{get, serde::json::Json, State};
+
+use crate::{
+    config::Settings,
+    db::{model::CollectionModel, search::SearchQuery},
+    services::search::{highlight, SearchResult},
+    utils::DBBag,
+};
+
+/// search looks for `q` in the titles, descriptions and categories of a `tenant`'s items.
+/// Results can be restricted to some `channel_id`s and to items created between `after` and `before` (ms).
+/// `limit` is brought within 1 and `max_search_limit`.
+#[get("/<tenant>/search?<q>&<channel_id>&<after>&<before>&<limit>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn search(
+    tenant: &str,
+    q: &str,
+    channel_id: Vec<i32>,
+    after: Option<i64>,
+    before: Option<i64>,
+    limit: Option<i64>,
+    db_bag: &State<Arc<DBBag>>,
+    settings: &State<Arc<Settings>>,
+) -> Json<Vec<SearchResult>> {
+    if q.trim().is_empty() {
+        return Json(vec![]);
+    }
+    let limit = limit
+        .unwrap_or(settings.default_item_per_feed)
+        .clamp(1, settings.max_search_limit.max(1));
+    let query = SearchQuery::new(q, limit)
+        .filter_eq("tenant", tenant)
+        .filter_in("channel_id", &channel_id)
+        .filter_range("create_date", after, before);
+    Json(
+        db_bag
+            .items_coll
+            .search(&query)
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .map(|hit| highlight(hit, q))
+            .collect(),
+    )
+}
@@ -0,0 +1,65 @@
+use std::sync::Arc;
+
+use mongodb::bson::doc;
+use rocket::{delete, get, http::Status, put, response::status, serde::json::Json, State};
+
+use crate::{
+    db::model::{CollectionModel, SortOrder},
+    entities::credential::{Credential, CredentialAuth, CredentialSummary},
+    services::credentials::reads_secrets,
+    utils::DBBag,
+};
+
+/// secrets lists the credentials of a `tenant`, without their secret values
+#[get("/<tenant>/secrets")]
+pub async fn secrets(tenant: &str, db_bag: &State<Arc<DBBag>>) -> Json<Vec<CredentialSummary>> {
+    Json(
+        db_bag
+            .secrets_coll
+            .find(doc! {"tenant": tenant}, ("name", SortOrder::ASC), None)
+            .await
+            .unwrap_or_default()
+            .iter()
+            .map(CredentialSummary::from)
+            .collect(),
+    )
+}
+
+/// put_secret creates or replaces the credential `name` of a `tenant`.
+/// Values read from the environment or a file (`env:`, `file:`) are refused.
+/// Only its summary is sent back.
+#[put("/<tenant>/secrets/<name>", format = "json", data = "<auth>")]
+pub async fn put_secret(
+    tenant: &str,
+    name: &str,
+    auth: Json<CredentialAuth>,
+    db_bag: &State<Arc<DBBag>>,
+) -> Result<Json<CredentialSummary>, status::Custom<String>> {
+    let auth = auth.into_inner();
+    if reads_secrets(&auth) {
+        return Err(status::Custom(
+            Status::BadRequest,
+            "env: and file: values can only be set by operators".to_string(),
+        ));
+    }
+    let credential = Credential {
+        tenant: tenant.to_string(),
+        name: name.to_string(),
+        auth,
+    };
+    db_bag
+        .secrets_coll
+        .upsert(&credential)
+        .await
+        .map_err(|err| status::Custom(Status::InternalServerError, err.0))?;
+    Ok(Json(CredentialSummary::from(&credential)))
+}
+
+#[delete("/<tenant>/secrets/<name>")]
+pub async fn delete_secret(tenant: &str, name: &str, db_bag: &State<Arc<DBBag>>) -> Status {
+    match db_bag.secrets_coll.delete(tenant, name).await {
+        Ok(true) => Status::NoContent,
+        Ok(false) => Status::NotFound,
+        Err(_) => Status::InternalServerError,
+    }
+}
@@ -0,0 +1,53 @@
+use std::sync::Arc;
+
+use mongodb::bson::doc;
+use rocket::{delete, get, http::Status, put, response::status, serde::json::Json, State};
+
+use crate::{
+    db::model::{CollectionModel, SortOrder},
+    entities::tag::Tag,
+    services::taxonomy::validate_tag,
+    utils::DBBag,
+};
+
+/// tags lists the taxonomy of a `tenant`
+#[get("/<tenant>/tags")]
+pub async fn tags(tenant: &str, db_bag: &State<Arc<DBBag>>) -> Json<Vec<Tag>> {
+    Json(
+        db_bag
+            .tags_coll
+            .find(doc! {"tenant": tenant}, ("name", SortOrder::ASC), None)
+            .await
+            .unwrap_or_default(),
+    )
+}
+
+/// put_tag creates or replaces the tag `name` of a `tenant`.
+/// Items stored before are not retagged.
+#[put("/<tenant>/tags/<name>", format = "json", data = "<tag>")]
+pub async fn put_tag(
+    tenant: &str,
+    name: &str,
+    tag: Json<Tag>,
+    db_bag: &State<Arc<DBBag>>,
+) -> Result<Json<Tag>, status::Custom<String>> {
+    let mut tag = tag.into_inner();
+    tag.tenant = tenant.to_string();
+    tag.name = name.to_string();
+    validate_tag(&tag).map_err(|err| status::Custom(Status::BadRequest, err.0))?;
+    db_bag
+        .tags_coll
+        .upsert(&tag)
+        .await
+        .map_err(|err| status::Custom(Status::InternalServerError, err.0))?;
+    Ok(Json(tag))
+}
+
+#[delete("/<tenant>/tags/<name>")]
+pub async fn delete_tag(tenant: &str, name: &str, db_bag: &State<Arc<DBBag>>) -> Status {
+    match db_bag.tags_coll.delete(tenant, name).await {
+        Ok(true) => Status::NoContent,
+        Ok(false) => Status::NotFound,
+        Err(_) => Status::InternalServerError,
+    }
+}
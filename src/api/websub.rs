@@ -0,0 +1,161 @@
+use std::sync::Arc;
+
+use chrono::Utc;
+use mongodb::bson::doc;
+use rocket::{
+    data::ToByteUnit,
+    get,
+    http::{ContentType, Status},
+    post,
+    request::{FromRequest, Outcome},
+    Data, FromForm, Request, State,
+};
+use uuid::Uuid;
+
+use crate::{
+    config::Settings,
+    db::model::CollectionModel,
+    entities::subscription::SubscriptionStatus,
+    services::{
+        charset::transcode,
+        rss::parse_rss,
+        websub::{accepts_verification, lease_expiry, signature_matches},
+    },
+    task::ingest_articles,
+    utils::{Clients, DBBag},
+};
+
+/// HubQuery holds the `hub.*` parameters of a hub's verification of intent
+#[derive(Debug, FromForm)]
+pub struct HubQuery<'r> {
+    mode: &'r str,
+    topic: &'r str,
+    challenge: Option<&'r str>,
+    lease_seconds: Option<i64>,
+    reason: Option<&'r str>,
+}
+
+/// HubSignature is the `X-Hub-Signature` header of a pushed feed
+pub struct HubSignature<'r>(&'r str);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for HubSignature<'r> {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match request.headers().get_one("X-Hub-Signature") {
+            Some(signature) => Outcome::Success(HubSignature(signature)),
+            None => Outcome::Forward(Status::Unauthorized),
+        }
+    }
+}
+
+/// verify_intent answers a hub checking that a channel did ask for its subscription,
+/// or telling that it was denied. Calls without the subscription's `token` are not found.
+#[get("/websub/<channel_id>/<token>?<hub>", rank = 1)]
+pub async fn verify_intent(
+    channel_id: i32,
+    token: &str,
+    hub: HubQuery<'_>,
+    db_bag: &State<Arc<DBBag>>,
+    settings: &State<Arc<Settings>>,
+) -> Result<String, Status> {
+    let subscription = db_bag
+        .subscriptions_coll
+        .get(channel_id)
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .filter(|subscription| !subscription.token.is_empty() && subscription.token == token)
+        .filter(|subscription| subscription.topic == hub.topic)
+        .ok_or(Status::NotFound)?;
+    let now = Utc::now().timestamp_millis();
+    match hub.mode {
+        "subscribe" => {
+            if !accepts_verification(&subscription, now, &settings.websub) {
+                return Err(Status::NotFound);
+            }
+            let challenge = hub.challenge.ok_or(Status::BadRequest)?;
+            let expires_at = lease_expiry(hub.lease_seconds, now, &settings.websub);
+            db_bag
+                .subscriptions_coll
+                .set_status(subscription.channel_id, SubscriptionStatus::Active, Some(expires_at), None)
+                .await
+                .map_err(|_| Status::InternalServerError)?;
+            Ok(challenge.to_string())
+        }
+        "denied" => {
+            db_bag
+                .subscriptions_coll
+                .set_status(subscription.channel_id, SubscriptionStatus::Denied, None, hub.reason)
+                .await
+                .map_err(|_| Status::InternalServerError)?;
+            Ok(String::new())
+        }
+        // unsubscriptions are never requested
+        _ => Err(Status::NotFound),
+    }
+}
+
+/// receive_push ingests the feed a hub pushes for a channel, gone once the channel is disabled.
+/// Pushes without a valid signature are acknowledged, as WebSub requires, but ignored.
+#[post("/websub/<channel_id>/<token>", data = "<body>", rank = 1)]
+#[allow(clippy::too_many_arguments)]
+pub async fn receive_push(
+    channel_id: i32,
+    token: &str,
+    body: Data<'_>,
+    signature: Option<HubSignature<'_>>,
+    content_type: Option<&ContentType>,
+    db_bag: &State<Arc<DBBag>>,
+    clients: &State<Arc<Clients>>,
+    settings: &State<Arc<Settings>>,
+) -> Status {
+    let Ok(Some(subscription)) = db_bag.subscriptions_coll.get(channel_id).await else {
+        return Status::Gone;
+    };
+    if subscription.token.is_empty() || subscription.token != token {
+        return Status::Gone;
+    }
+    let body = match body.open(settings.http.max_body_bytes.bytes()).into_bytes().await {
+        Ok(body) if body.is_complete() => body.into_inner(),
+        _ => return Status::PayloadTooLarge,
+    };
+    let Some(HubSignature(signature)) = signature else {
+        return Status::Accepted;
+    };
+    if !signature_matches(&subscription.secret, &body, signature) {
+        println!("[WARN] websub push for channel {} with a bad signature", channel_id);
+        return Status::Accepted;
+    }
+    let Some(channel) = db_bag
+        .channels_coll
+        .find(doc! {"id": channel_id}, None, 1)
+        .await
+        .unwrap_or_default()
+        .pop()
+        .filter(|channel| channel.disabled_at.is_none())
+    else {
+        return Status::Gone;
+    };
+    let content_type = content_type.map(|content_type| content_type.to_string());
+    let (feed, _) = transcode(&body, content_type.as_deref());
+    let articles = match parse_rss(&feed, &channel) {
+        Ok((articles, _)) => articles,
+        Err(err) => {
+            println!("[ERR ] websub push for channel {}: {:?}", channel_id, err);
+            return Status::Accepted;
+        }
+    };
+    let report = ingest_articles(&articles, db_bag, clients, &channel, settings, Uuid::new_v4()).await;
+    if let Err(err) = db_bag.channels_coll.set_refresh_report(channel_id, &report).await {
+        println!("[ERR ] {:?}", err);
+    }
+    if let Err(err) = db_bag
+        .subscriptions_coll
+        .set_last_push(channel_id, report.date)
+        .await
+    {
+        println!("[ERR ] {:?}", err);
+    }
+    Status::NoContent
+}
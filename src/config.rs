@@ -1,16 +1,128 @@
 use config::{Config, ConfigError, Environment, File};
 use serde::Deserialize;
 
+#[derive(Debug, Deserialize, Clone)]
+pub struct CollectionsSettings {
+    pub channels: String,
+    pub items: String,
+    pub counters: String,
+    pub migrations: String,
+    pub tags: String,
+    pub secrets: String,
+    pub subscriptions: String,
+}
+
+impl CollectionsSettings {
+    pub fn names(&self) -> Vec<&String> {
+        vec![
+            &self.channels,
+            &self.items,
+            &self.counters,
+            &self.migrations,
+            &self.tags,
+            &self.secrets,
+            &self.subscriptions,
+        ]
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct DuplicatesSettings {
+    pub enabled: bool,
+    // max hamming distance between two fingerprints of near-duplicates (out of 64 bits)
+    pub max_distance: u32,
+    // how far back stored items are compared to new ones
+    pub window_hours: i64,
+    // most recent stored items compared to new ones, within the window
+    pub max_candidates: i64,
+}
+
+/// HttpSettings set up the client shared by every fetcher, see `services::http`
+#[derive(Debug, Deserialize, Clone)]
+pub struct HttpSettings {
+    pub user_agent: String,
+    pub connect_timeout_ms: u64,
+    // whole request, body reading included
+    pub timeout_ms: u64,
+    pub max_body_bytes: usize,
+    // http(s) proxy url, e.g. "http://proxy:3128"
+    #[serde(default)]
+    pub proxy: Option<String>,
+    // path of a pem bundle of extra root certificates
+    #[serde(default)]
+    pub ca_bundle: Option<String>,
+    pub max_redirects: usize,
+    // accepts gzip, brotli and deflate encoded bodies
+    pub compression: bool,
+}
+
+/// WebSubSettings set up push subscriptions to the hubs feeds advertise, see `services::websub`
+#[derive(Debug, Deserialize, Clone)]
+pub struct WebSubSettings {
+    pub enabled: bool,
+    // public url of patishie's routes, e.g. "https://patishie.example.com/patishie", hubs call back under it
+    pub callback_url: String,
+    // lease asked to hubs, in seconds
+    pub lease_seconds: i64,
+    // leases are renewed when expiring within this many seconds
+    pub renew_before: i64,
+    // seconds before a subscription the hub never verified is requested again
+    pub verify_timeout: i64,
+    // seconds before a subscription the hub denied is requested again
+    pub retry_denied_after: i64,
+    // minimum refresh frequency (ms) of channels with an active subscription
+    pub poll_interval: i64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct BakerySettings {
+    pub timeout_ms: u64,
+    // retries of a call while bakery is unavailable (unreachable, timeout, 5xx or 429)
+    pub retries: u32,
+    // base delay of the exponential backoff between retries
+    pub backoff_ms: u64,
+    // consecutive failed calls opening the circuit breaker, pausing bakery channels
+    pub breaker_threshold: u32,
+    pub breaker_cooldown_ms: i64,
+    // seconds between two scrape triggers of a same host, on top of `bakery_trigger_cooldown`
+    pub host_cooldown: i64,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Settings {
     // database: DatabaseSettings,
     pub api_path: String,
+    pub bakery: BakerySettings,
+    pub http: HttpSettings,
+    pub websub: WebSubSettings,
     pub databases: Vec<String>,
+    // database holding patishie's collections, must be one of `databases`
+    pub database: String,
+    pub collections: CollectionsSettings,
     pub db_path: String,
     pub app_name: String,
+    // seconds between two bakery scrape triggers
     pub bakery_trigger_cooldown: i64,
     pub default_item_per_feed: i64,
+    // maximum amount of results of a search
+    pub max_search_limit: i64,
+    // maximum amount of items listed at once
+    pub max_items_limit: i64,
     pub default_main_sleep: u64,
+    pub migrate_on_startup: bool,
+    // maximum amount of channels refreshed per tenant and per cycle, 0 for no limit
+    pub max_channels_per_tenant: usize,
+    // max amount of chars of an item's plain text excerpt, 0 for no limit
+    pub excerpt_length: usize,
+    // fetches new articles' pages to dedupe them on their <link rel="canonical">
+    pub follow_canonical_links: bool,
+    pub duplicates: DuplicatesSettings,
+    // minimum confidence (0 to 1) of a detected language for it to be kept
+    pub language_min_confidence: f64,
+    // consecutive refreshes permanently redirected to the same url before a channel's url is updated
+    pub redirect_confirmations: u32,
+    // cap (ms) on the refresh interval derived from feeds' ttl, syndication and cache hints
+    pub max_hinted_refresh_interval: i64,
 }
 
 impl Settings {
@@ -31,6 +143,120 @@ impl Settings {
         s.merge(Environment::with_prefix("APP").separator("__"))?;
 
         // Now that we're done, let's access our configuration
-        s.try_into()
+        let settings: Settings = s.try_into()?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// validate checks that `database` is one of the databases opened by the `Handle`,
+    /// and that collection names are set and distinct.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !self.databases.contains(&self.database) {
+            return Err(ConfigError::Message(format!(
+                "database \"{}\" is not part of databases {:?}",
+                self.database, self.databases
+            )));
+        }
+        let mut names = self.collections.names();
+        if names.iter().any(|name| name.is_empty()) {
+            return Err(ConfigError::Message(
+                "collection names cannot be empty".to_string(),
+            ));
+        }
+        names.sort();
+        names.dedup();
+        if names.len() != self.collections.names().len() {
+            return Err(ConfigError::Message(format!(
+                "collection names must be distinct: {:?}",
+                self.collections
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> Settings {
+        Settings {
+            api_path: "http://localhost:8084".to_string(),
+            bakery: BakerySettings {
+                timeout_ms: 30000,
+                retries: 2,
+                backoff_ms: 500,
+                breaker_threshold: 5,
+                breaker_cooldown_ms: 60000,
+                host_cooldown: 30,
+            },
+            http: HttpSettings {
+                user_agent: "patishie".to_string(),
+                connect_timeout_ms: 10000,
+                timeout_ms: 30000,
+                max_body_bytes: 10_000_000,
+                proxy: None,
+                ca_bundle: None,
+                max_redirects: 10,
+                compression: true,
+            },
+            websub: WebSubSettings {
+                enabled: false,
+                callback_url: "".to_string(),
+                lease_seconds: 864_000,
+                renew_before: 86_400,
+                verify_timeout: 3_600,
+                retry_denied_after: 86_400,
+                poll_interval: 21_600_000,
+            },
+            databases: vec!["panya".to_string()],
+            database: "panya".to_string(),
+            collections: CollectionsSettings {
+                channels: "channels".to_string(),
+                items: "items".to_string(),
+                counters: "counters".to_string(),
+                migrations: "migrations".to_string(),
+                tags: "tags".to_string(),
+                secrets: "secrets".to_string(),
+                subscriptions: "subscriptions".to_string(),
+            },
+            db_path: "mongodb://localhost:27017".to_string(),
+            app_name: "patishie".to_string(),
+            bakery_trigger_cooldown: 5,
+            default_item_per_feed: 15,
+            max_search_limit: 100,
+            max_items_limit: 500,
+            default_main_sleep: 200,
+            migrate_on_startup: true,
+            max_channels_per_tenant: 0,
+            excerpt_length: 280,
+            follow_canonical_links: false,
+            duplicates: DuplicatesSettings {
+                enabled: true,
+                max_distance: 6,
+                window_hours: 48,
+                max_candidates: 2000,
+            },
+            language_min_confidence: 0.5,
+            redirect_confirmations: 3,
+            max_hinted_refresh_interval: 86_400_000,
+        }
+    }
+
+    #[test]
+    fn test_validate_settings() {
+        assert!(settings().validate().is_ok());
+
+        let mut unknown_db = settings();
+        unknown_db.database = "panya_staging".to_string();
+        assert!(unknown_db.validate().is_err());
+
+        let mut duplicated = settings();
+        duplicated.collections.items = "channels".to_string();
+        assert!(duplicated.validate().is_err());
+
+        let mut empty = settings();
+        empty.collections.counters = "".to_string();
+        assert!(empty.validate().is_err());
     }
 }
@@ -1,9 +1,85 @@
-use crate::entities::potential_articles::PotentialArticle;
+use serde::Deserialize;
 
-pub fn to_articles(raw_data: &str) -> Vec<PotentialArticle>
-{
-    serde_json::from_str::<Vec<PotentialArticle>>(raw_data).unwrap_or_else(|err| {
-        eprintln!("could not deserialize into articles: {}", err);
-        vec![]
+use crate::{
+    entities::{
+        bakery_response::{BakeryResponse, BAKERY_RESPONSE_VERSION},
+        potential_articles::PotentialArticle,
+    },
+    error::Error,
+};
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawBakeryResponse {
+    Envelope(BakeryResponse),
+    // unversioned bakeries
+    Articles(Vec<PotentialArticle>),
+}
+
+/// to_bakery_response reads an answer of bakery, either a versioned envelope or a bare array of articles.
+/// Answers matching neither are an error, rather than an empty list of articles.
+pub fn to_bakery_response(raw_data: &str) -> Result<BakeryResponse, Error> {
+    let raw = serde_json::from_str::<RawBakeryResponse>(raw_data).map_err(|_| {
+        // untagged enums hide why each variant failed, the envelope's error is the most telling
+        let reason = serde_json::from_str::<BakeryResponse>(raw_data)
+            .err()
+            .map(|err| err.to_string())
+            .unwrap_or_default();
+        Error(format!("could not deserialize bakery response: {}", reason))
+    })?;
+    Ok(match raw {
+        RawBakeryResponse::Envelope(mut response) => {
+            if response.version > BAKERY_RESPONSE_VERSION {
+                response.metadata.warnings.push(format!(
+                    "bakery response version {} is newer than {}",
+                    response.version, BAKERY_RESPONSE_VERSION
+                ));
+            }
+            response
+        }
+        RawBakeryResponse::Articles(articles) => BakeryResponse {
+            version: 0,
+            articles,
+            ..Default::default()
+        },
     })
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ARTICLE: &str = r#"{"link": "https://a.com/1", "img": "", "desc": "", "title": "a", "create_date": 1721206405128, "channel_name": null, "channel_id": null, "categories": null}"#;
+
+    #[test]
+    fn test_bare_array() {
+        let response = to_bakery_response(&format!("[{}]", ARTICLE)).unwrap();
+        assert_eq!(response.version, 0);
+        assert_eq!(response.articles[0].link, "https://a.com/1");
+        assert_eq!(response.metadata.warnings.len(), 0);
+    }
+
+    #[test]
+    fn test_envelope() {
+        let response = to_bakery_response(&format!(
+            r#"{{"version": 1, "articles": [{}], "metadata": {{"status": "ok", "selector": "article h2 a", "page_title": "NEWS WEB EASY", "warnings": ["no date found"]}}}}"#,
+            ARTICLE
+        ))
+        .unwrap();
+        assert_eq!(response.articles.len(), 1);
+        assert_eq!(response.metadata.selector.as_deref(), Some("article h2 a"));
+        assert_eq!(response.metadata.warnings, vec!["no date found"]);
+
+        let empty = to_bakery_response(r#"{"version": 2, "metadata": {"status": "blocked"}}"#).unwrap();
+        assert!(empty.articles.is_empty());
+        assert_eq!(empty.metadata.status.as_deref(), Some("blocked"));
+        assert_eq!(empty.metadata.warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_schema_mismatch() {
+        let err = to_bakery_response(r#"{"version": 1, "articles": [{"link": 3}]}"#).unwrap_err();
+        assert!(err.0.starts_with("could not deserialize bakery response: invalid type"));
+        assert!(to_bakery_response("<html>502 Bad Gateway</html>").is_err());
+    }
+}
@@ -5,12 +5,19 @@ use super::{
 use crate::{
     entities::{
         channel::{new_with_seq_db, Channel},
+        redirect::{PendingRedirect, UrlChange},
+        refresh_hints::RefreshHints,
+        refresh_report::RefreshReport,
         source_type::SourceType,
     },
     error::Error,
 };
 use chrono::{DateTime, Utc};
-use mongodb::{bson::doc, results::InsertManyResult, Collection, Database};
+use mongodb::{
+    bson::{doc, to_bson},
+    results::InsertManyResult,
+    Collection, Database,
+};
 use serde::Serialize;
 use std::{fmt::Debug, sync::Arc};
 
@@ -68,6 +75,105 @@ impl Channels<Channel> {
             .and(Some(refresh_time))
     }
 
+    /// set_refresh_report records the outcome of the last refresh of a channel
+    pub async fn set_refresh_report(&self, channel_id: i32, report: &RefreshReport) -> Result<(), Error> {
+        let report = to_bson(report).map_err(|err| Error(err.to_string()))?;
+        self.collection()
+            .update_one(
+                doc! {"id": channel_id},
+                doc! {"$set": {"last_refresh_report": report}},
+                None,
+            )
+            .await
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    pub async fn set_language(&self, channel_id: i32, language: &str) -> Result<(), Error> {
+        self.collection()
+            .update_one(
+                doc! {"id": channel_id},
+                doc! {"$set": {"language": language}},
+                None,
+            )
+            .await
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    /// set_pending_redirect records, or clears, the permanent redirect a channel's url is going through
+    pub async fn set_pending_redirect(
+        &self,
+        channel_id: i32,
+        pending: Option<&PendingRedirect>,
+    ) -> Result<(), Error> {
+        let pending = to_bson(&pending).map_err(|err| Error(err.to_string()))?;
+        self.collection()
+            .update_one(
+                doc! {"id": channel_id},
+                doc! {"$set": {"pending_redirect": pending}},
+                None,
+            )
+            .await
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    /// move_url points a channel to `url`, keeping its current one in its url history
+    pub async fn move_url(&self, channel_id: i32, url: &str, previous: &UrlChange) -> Result<(), Error> {
+        let previous = to_bson(previous).map_err(|err| Error(err.to_string()))?;
+        self.collection()
+            .update_one(
+                doc! {"id": channel_id},
+                vec![doc! {"$set": {
+                    "url": url,
+                    "pending_redirect": null,
+                    "url_history": {"$concatArrays": [{"$ifNull": ["$url_history", []]}, [previous]]},
+                }}],
+                None,
+            )
+            .await
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    /// set_schedule records the refresh hints of a channel along with the frequency derived from them
+    pub async fn set_schedule(
+        &self,
+        channel_id: i32,
+        refresh_frequency: i32,
+        base_refresh_frequency: i32,
+        hints: &RefreshHints,
+    ) -> Result<(), Error> {
+        let hints = to_bson(hints).map_err(|err| Error(err.to_string()))?;
+        self.collection()
+            .update_one(
+                doc! {"id": channel_id},
+                doc! {"$set": {
+                    "refresh_frequency": refresh_frequency,
+                    "base_refresh_frequency": base_refresh_frequency,
+                    "refresh_hints": hints,
+                }},
+                None,
+            )
+            .await
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    /// disable stops the refreshes of a channel
+    pub async fn disable(&self, channel_id: i32, date: i64) -> Result<(), Error> {
+        self.collection()
+            .update_one(
+                doc! {"id": channel_id},
+                doc! {"$set": {"disabled_at": date}},
+                None,
+            )
+            .await
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
     pub async fn update_refresh_now(
         &self,
         channel_id: impl Into<Option<i32>>,
@@ -78,12 +184,12 @@ impl Channels<Channel> {
             .await
     }
 
-    pub fn new(handle: Arc<Handle>, db_name: &str) -> Result<Self, Error> {
+    pub fn new(handle: Arc<Handle>, db_name: &str, collection_name: &str) -> Result<Self, Error> {
         let collection = (match handle.database(db_name) {
             Some(res) => res,
-            None => return Err(Error("no database found".to_string())),
+            None => return Err(Error(format!("no database found: {}", db_name))),
         })
-        .collection::<Channel>("channels");
+        .collection::<Channel>(collection_name);
         Ok(Channels {
             db_name: db_name.to_string(),
             handle,
@@ -104,23 +210,31 @@ impl<P: PartialEq, T: CollectionModelConstraint<P>> CollectionModel<P, T> for Ch
     fn get_database(&self) -> Option<&Database> {
         self.handle.database(&self.db_name)
     }
+
+    fn get_counters_collection_name(&self) -> String {
+        self.handle.counters_collection_name().to_string()
+    }
 }
 
+/// get_channel_id finds the channel of a tenant with the same url, current or previous,
+/// or else with the same name. A new channel is created when none matches.
 pub async fn get_channel_id(
     channels_coll: &Channels<Channel>,
+    tenant: &str,
     channel_name: &str,
     channel_url: &str,
     source_type: SourceType,
 ) -> Result<i32, Error> {
-    match channels_coll
-        .find(doc! {"name": channel_name}, None, 1)
-        .await
-        .unwrap_or_default()
-        .pop()
-    {
-        Some(p) => Ok(p.id),
-        None => new_with_seq_db(channel_name, channel_url, source_type, channels_coll)
-            .await
-            .and_then(|el| Ok(el.id)),
+    let filters = [
+        doc! {"tenant": tenant, "$or": [{"url": channel_url}, {"url_history.url": channel_url}]},
+        doc! {"tenant": tenant, "name": channel_name},
+    ];
+    for filter in filters {
+        if let Some(p) = channels_coll.find(filter, None, 1).await.unwrap_or_default().pop() {
+            return Ok(p.id);
+        }
     }
+    new_with_seq_db(tenant, channel_name, channel_url, source_type, channels_coll)
+        .await
+        .map(|el| el.id)
 }
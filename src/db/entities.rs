@@ -42,7 +42,25 @@ impl PrimaryID<String> for Refresh {
         Some(self.url.clone())
     }
 }
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AppliedMigration {
+    pub version: i32,
+    pub name: String,
+    // ms
+    pub applied_at: i64,
+}
 
+impl FieldSort<String> for AppliedMigration {
+    fn sort_by_value(&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl PrimaryID<i32> for AppliedMigration {
+    fn get_primary_id(&self) -> Option<i32> {
+        Some(self.version)
+    }
+}
 
 #[derive(Deserialize)]
 pub enum AscDesc {
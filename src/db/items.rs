@@ -3,7 +3,13 @@ use super::{
     mongo::Handle,
 };
 use crate::error::Error;
-use mongodb::{bson::doc, results::InsertManyResult, Collection, Database, IndexModel};
+use futures::StreamExt;
+use mongodb::{
+    bson::{doc, Document},
+    options::FindOptions,
+    results::InsertManyResult,
+    Collection, Database, IndexModel,
+};
 use serde::Serialize;
 use std::{fmt::Debug, sync::Arc};
 
@@ -26,16 +32,88 @@ impl<T: CollectionModelConstraint<i32>> Items<T> {
         CollectionModel::<i32, T>::insert_many(self, data).await
     }
 
+    /// set_cluster_id links the items of a `tenant` matching `canonical_link` to a cluster
+    pub async fn set_cluster_id(
+        &self,
+        tenant: &str,
+        canonical_link: &str,
+        cluster_id: &str,
+    ) -> Result<(), Error> {
+        self.collection()
+            .update_many(
+                doc! {"tenant": tenant, "canonical_link": canonical_link},
+                doc! {"$set": {"cluster_id": cluster_id}},
+                None,
+            )
+            .await
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    /// find_known_links returns, among `keys`, those already stored for a `tenant`
+    /// as the canonical link or the link key of an item
+    pub async fn find_known_links(&self, tenant: &str, keys: &[String]) -> Vec<String> {
+        let mut cursor = match self
+            .collection()
+            .clone_with_type::<Document>()
+            .find(
+                doc! {
+                    "tenant": tenant,
+                    "$or": [{"canonical_link": {"$in": keys}}, {"link_key": {"$in": keys}}],
+                },
+                FindOptions::builder()
+                    .projection(doc! {"canonical_link": 1, "link_key": 1})
+                    .build(),
+            )
+            .await
+        {
+            Ok(c) => c,
+            Err(_) => return vec![],
+        };
+        let mut results = vec![];
+        while let Some(Ok(res)) = cursor.next().await {
+            for field in ["canonical_link", "link_key"] {
+                if let Ok(key) = res.get_str(field) {
+                    results.push(key.to_string());
+                }
+            }
+        }
+        results
+    }
+
+    /// find_permalink_guids returns, among `guids`, the permalink guids already stored for a `tenant`
+    pub async fn find_permalink_guids(&self, tenant: &str, guids: &[&str]) -> Vec<String> {
+        let mut cursor = match self
+            .collection()
+            .clone_with_type::<Document>()
+            .find(
+                doc! {"tenant": tenant, "guid_is_permalink": true, "guid": {"$in": guids}},
+                FindOptions::builder().projection(doc! {"guid": 1}).build(),
+            )
+            .await
+        {
+            Ok(c) => c,
+            Err(_) => return vec![],
+        };
+        let mut results = vec![];
+        while let Some(Ok(res)) = cursor.next().await {
+            if let Ok(guid) = res.get_str("guid") {
+                results.push(guid.to_string());
+            }
+        }
+        results
+    }
+
     pub fn get_database_name(&self) -> &String {
         &self.db_name
     }
 
-    pub fn new(handle: Arc<Handle>, db_name: &str) -> Result<Self, Error> {
+    pub fn new(handle: Arc<Handle>, db_name: &str, collection_name: &str) -> Result<Self, Error> {
         let collection = (match handle.database(db_name) {
             Some(res) => res,
-            None => return Err(Error("no database found".to_string())),
+            None => return Err(Error(format!("no database found: {}", db_name))),
         })
-        .collection::<T>("items");
+        .collection::<T>(collection_name);
         Ok(Items {
             db_name: db_name.to_string(),
             handle,
@@ -56,4 +134,8 @@ impl<P: PartialEq, T: CollectionModelConstraint<P>> CollectionModel<P, T> for It
     fn get_database(&self) -> Option<&Database> {
         self.handle.database(&self.db_name)
     }
+
+    fn get_counters_collection_name(&self) -> String {
+        self.handle.counters_collection_name().to_string()
+    }
 }
\ No newline at end of file
@@ -0,0 +1,435 @@
+use super::{
+    entities::AppliedMigration,
+    model::{CollectionModel, CollectionModelConstraint},
+    mongo::Handle,
+};
+use crate::{
+    config::CollectionsSettings, entities::channel::DEFAULT_TENANT, error::Error,
+    services::link::canonical_url, utils::SECONDS_TIMESTAMP_CEILING,
+};
+use chrono::Utc;
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, Document},
+    options::IndexOptions,
+    Collection, Database, IndexModel,
+};
+use serde::Serialize;
+use std::{fmt::Debug, sync::Arc};
+
+/// Migration lists every schema change patishie knows about.
+/// The version of a migration must never change once it shipped,
+/// new migrations are appended at the end of `MIGRATIONS`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Migration {
+    BackfillChannelFields,
+    NormalizeTimestamps,
+    CreateIndexes,
+    BackfillTenant,
+    CreateTextIndex,
+    BackfillCanonicalLinks,
+    CreateClusterIndex,
+    CreateGuidIndex,
+    CreateTagIndexes,
+    CreateSecretIndex,
+    CreateSubscriptionIndex,
+    CreateLinkKeyIndex,
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration::BackfillChannelFields,
+    Migration::NormalizeTimestamps,
+    Migration::CreateIndexes,
+    Migration::BackfillTenant,
+    Migration::CreateTextIndex,
+    Migration::BackfillCanonicalLinks,
+    Migration::CreateClusterIndex,
+    Migration::CreateGuidIndex,
+    Migration::CreateTagIndexes,
+    Migration::CreateSecretIndex,
+    Migration::CreateSubscriptionIndex,
+    Migration::CreateLinkKeyIndex,
+];
+
+impl Migration {
+    pub fn version(&self) -> i32 {
+        match self {
+            Migration::BackfillChannelFields => 1,
+            Migration::NormalizeTimestamps => 2,
+            Migration::CreateIndexes => 3,
+            Migration::BackfillTenant => 4,
+            Migration::CreateTextIndex => 5,
+            Migration::BackfillCanonicalLinks => 6,
+            Migration::CreateClusterIndex => 7,
+            Migration::CreateGuidIndex => 8,
+            Migration::CreateTagIndexes => 9,
+            Migration::CreateSecretIndex => 10,
+            Migration::CreateSubscriptionIndex => 11,
+            Migration::CreateLinkKeyIndex => 12,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Migration::BackfillChannelFields => "backfill_channel_fields",
+            Migration::NormalizeTimestamps => "normalize_timestamps",
+            Migration::CreateIndexes => "create_indexes",
+            Migration::BackfillTenant => "backfill_tenant",
+            Migration::CreateTextIndex => "create_text_index",
+            Migration::BackfillCanonicalLinks => "backfill_canonical_links",
+            Migration::CreateClusterIndex => "create_cluster_index",
+            Migration::CreateGuidIndex => "create_guid_index",
+            Migration::CreateTagIndexes => "create_tag_indexes",
+            Migration::CreateSecretIndex => "create_secret_index",
+            Migration::CreateSubscriptionIndex => "create_subscription_index",
+            Migration::CreateLinkKeyIndex => "create_link_key_index",
+        }
+    }
+
+    /// up applies the migration onto `database`.
+    /// Every migration must be safe to run again on an already migrated database.
+    pub async fn up(
+        self,
+        database: &Database,
+        collections: &CollectionsSettings,
+    ) -> Result<(), Error> {
+        match self {
+            Migration::BackfillChannelFields => {
+                let channels = database.collection::<Document>(&collections.channels);
+                channels
+                    .update_many(
+                        doc! {"last_successful_refresh": {"$exists": false}},
+                        vec![doc! {"$set": {"last_successful_refresh": "$last_refresh"}}],
+                        None,
+                    )
+                    .await?;
+                channels
+                    .update_many(
+                        doc! {"base_refresh_frequency": {"$exists": false}},
+                        vec![doc! {"$set": {"base_refresh_frequency": "$refresh_frequency"}}],
+                        None,
+                    )
+                    .await?;
+            }
+            Migration::NormalizeTimestamps => {
+                // dates stored in seconds are turned into milliseconds
+                database
+                    .collection::<Document>(&collections.items)
+                    .update_many(
+                        doc! {"create_date": {"$gt": 0, "$lt": SECONDS_TIMESTAMP_CEILING}},
+                        vec![doc! {"$set": {"create_date": {"$multiply": ["$create_date", 1000_i64]}}}],
+                        None,
+                    )
+                    .await?;
+            }
+            Migration::CreateIndexes => {
+                database
+                    .collection::<Document>(&collections.items)
+                    .create_indexes(
+                        vec![
+                            IndexModel::builder().keys(doc! {"create_date": -1}).build(),
+                            IndexModel::builder().keys(doc! {"link": 1}).build(),
+                            IndexModel::builder()
+                                .keys(doc! {"channel_id": 1, "create_date": -1})
+                                .build(),
+                        ],
+                        None,
+                    )
+                    .await?;
+                database
+                    .collection::<Document>(&collections.channels)
+                    .create_indexes(
+                        vec![
+                            IndexModel::builder()
+                                .keys(doc! {"id": 1})
+                                .options(IndexOptions::builder().unique(true).build())
+                                .build(),
+                            IndexModel::builder().keys(doc! {"name": 1}).build(),
+                        ],
+                        None,
+                    )
+                    .await?;
+            }
+            Migration::BackfillTenant => {
+                for coll_name in [&collections.channels, &collections.items] {
+                    database
+                        .collection::<Document>(coll_name)
+                        .update_many(
+                            doc! {"tenant": {"$exists": false}},
+                            doc! {"$set": {"tenant": DEFAULT_TENANT}},
+                            None,
+                        )
+                        .await?;
+                }
+                database
+                    .collection::<Document>(&collections.items)
+                    .create_index(
+                        IndexModel::builder()
+                            .keys(doc! {"tenant": 1, "create_date": -1})
+                            .build(),
+                        None,
+                    )
+                    .await?;
+                database
+                    .collection::<Document>(&collections.channels)
+                    .create_index(
+                        IndexModel::builder().keys(doc! {"tenant": 1, "name": 1}).build(),
+                        None,
+                    )
+                    .await?;
+            }
+            Migration::CreateTextIndex => {
+                database
+                    .collection::<Document>(&collections.items)
+                    .create_index(
+                        IndexModel::builder()
+                            .keys(doc! {"title": "text", "desc": "text", "categories": "text"})
+                            .options(
+                                IndexOptions::builder()
+                                    .name("items_text".to_string())
+                                    .weights(doc! {"title": 10, "categories": 5, "desc": 1})
+                                    .default_language("none".to_string())
+                                    .build(),
+                            )
+                            .build(),
+                        None,
+                    )
+                    .await?;
+            }
+            Migration::BackfillCanonicalLinks => {
+                let items = database.collection::<Document>(&collections.items);
+                let mut cursor = items
+                    .find(doc! {"canonical_link": {"$exists": false}}, None)
+                    .await?;
+                while let Some(item) = cursor.try_next().await? {
+                    let (Ok(id), Ok(link)) = (item.get_object_id("_id"), item.get_str("link")) else {
+                        continue;
+                    };
+                    let canonical = canonical_url(link, None).unwrap_or_else(|_| link.to_string());
+                    items
+                        .update_one(
+                            doc! {"_id": id},
+                            doc! {"$set": {"canonical_link": canonical}},
+                            None,
+                        )
+                        .await?;
+                }
+                items
+                    .create_index(
+                        IndexModel::builder()
+                            .keys(doc! {"tenant": 1, "canonical_link": 1})
+                            .build(),
+                        None,
+                    )
+                    .await?;
+            }
+            Migration::CreateClusterIndex => {
+                database
+                    .collection::<Document>(&collections.items)
+                    .create_index(
+                        IndexModel::builder()
+                            .keys(doc! {"tenant": 1, "cluster_id": 1})
+                            .options(IndexOptions::builder().sparse(true).build())
+                            .build(),
+                        None,
+                    )
+                    .await?;
+            }
+            Migration::CreateGuidIndex => {
+                database
+                    .collection::<Document>(&collections.items)
+                    .create_index(
+                        IndexModel::builder()
+                            .keys(doc! {"tenant": 1, "guid": 1})
+                            .options(IndexOptions::builder().sparse(true).build())
+                            .build(),
+                        None,
+                    )
+                    .await?;
+            }
+            Migration::CreateTagIndexes => {
+                database
+                    .collection::<Document>(&collections.tags)
+                    .create_index(
+                        IndexModel::builder()
+                            .keys(doc! {"tenant": 1, "name": 1})
+                            .options(IndexOptions::builder().unique(true).build())
+                            .build(),
+                        None,
+                    )
+                    .await?;
+                database
+                    .collection::<Document>(&collections.items)
+                    .create_index(
+                        IndexModel::builder()
+                            .keys(doc! {"tenant": 1, "tags": 1, "create_date": -1})
+                            .build(),
+                        None,
+                    )
+                    .await?;
+            }
+            Migration::CreateSecretIndex => {
+                database
+                    .collection::<Document>(&collections.secrets)
+                    .create_index(
+                        IndexModel::builder()
+                            .keys(doc! {"tenant": 1, "name": 1})
+                            .options(IndexOptions::builder().unique(true).build())
+                            .build(),
+                        None,
+                    )
+                    .await?;
+            }
+            Migration::CreateSubscriptionIndex => {
+                database
+                    .collection::<Document>(&collections.subscriptions)
+                    .create_index(
+                        IndexModel::builder()
+                            .keys(doc! {"channel_id": 1})
+                            .options(IndexOptions::builder().unique(true).build())
+                            .build(),
+                        None,
+                    )
+                    .await?;
+            }
+            Migration::CreateLinkKeyIndex => {
+                database
+                    .collection::<Document>(&collections.items)
+                    .create_index(
+                        IndexModel::builder()
+                            .keys(doc! {"tenant": 1, "link_key": 1})
+                            .options(IndexOptions::builder().sparse(true).build())
+                            .build(),
+                        None,
+                    )
+                    .await?;
+            }
+        };
+        Ok(())
+    }
+}
+
+/// pending_migrations returns, ordered by version, the migrations which version is not in `applied`.
+pub fn pending_migrations(applied: &[i32]) -> Vec<Migration> {
+    let mut pending: Vec<Migration> = MIGRATIONS
+        .iter()
+        .filter(|m| !applied.contains(&m.version()))
+        .copied()
+        .collect();
+    pending.sort_by_key(|m| m.version());
+    pending
+}
+
+#[derive(Debug)]
+pub struct Migrations<T: Serialize> {
+    collection: Collection<T>,
+    handle: Arc<Handle>,
+    db_name: String,
+}
+
+impl Migrations<AppliedMigration> {
+    pub fn new(handle: Arc<Handle>, db_name: &str, collection_name: &str) -> Result<Self, Error> {
+        let collection = (match handle.database(db_name) {
+            Some(res) => res,
+            None => return Err(Error(format!("no database found: {}", db_name))),
+        })
+        .collection::<AppliedMigration>(collection_name);
+        Ok(Migrations {
+            db_name: db_name.to_string(),
+            handle,
+            collection,
+        })
+    }
+
+    /// applied_versions lists the recorded migrations. Failing to read them is an error,
+    /// never an empty list, so that no migration is applied twice.
+    pub async fn applied_versions(&self) -> Result<Vec<i32>, Error> {
+        let applied: Vec<AppliedMigration> = self.collection.find(None, None).await?.try_collect().await?;
+        Ok(applied.iter().map(|m| m.version).collect())
+    }
+
+    pub async fn pending(&self) -> Result<Vec<Migration>, Error> {
+        Ok(pending_migrations(&self.applied_versions().await?))
+    }
+
+    /// run_pending applies, in order, every migration not yet recorded in the migrations collection.
+    /// It stops at the first failing migration, leaving the following ones pending.
+    /// Returns the versions applied during this run.
+    pub async fn run_pending(&self, collections: &CollectionsSettings) -> Result<Vec<i32>, Error> {
+        let database = self
+            .handle
+            .database(&self.db_name)
+            .ok_or_else(|| Error::string("no database found"))?;
+        let mut applied = vec![];
+        for migration in self.pending().await? {
+            eprintln!(
+                "({}) Applying migration {} {}",
+                Utc::now().timestamp_millis(),
+                migration.version(),
+                migration.name()
+            );
+            migration.up(database, collections).await.map_err(|err| {
+                Error(format!(
+                    "migration {} {} failed: {}",
+                    migration.version(),
+                    migration.name(),
+                    err
+                ))
+            })?;
+            self.collection
+                .insert_one(
+                    AppliedMigration {
+                        version: migration.version(),
+                        name: migration.name().to_string(),
+                        applied_at: Utc::now().timestamp_millis(),
+                    },
+                    None,
+                )
+                .await?;
+            applied.push(migration.version());
+        }
+        Ok(applied)
+    }
+}
+
+impl<P: PartialEq, T: CollectionModelConstraint<P>> CollectionModel<P, T> for Migrations<T> {
+    fn collection(&self) -> &Collection<T> {
+        &self.collection
+    }
+
+    fn get_collection_name(&self) -> String {
+        self.collection.name().to_string()
+    }
+
+    fn get_database(&self) -> Option<&Database> {
+        self.handle.database(&self.db_name)
+    }
+
+    fn get_counters_collection_name(&self) -> String {
+        self.handle.counters_collection_name().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migration_versions_are_unique_and_ordered() {
+        let versions: Vec<i32> = MIGRATIONS.iter().map(|m| m.version()).collect();
+        let mut sorted = versions.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(versions, sorted);
+    }
+
+    #[test]
+    fn test_pending_migrations() {
+        assert_eq!(pending_migrations(&[]), MIGRATIONS.to_vec());
+        assert_eq!(
+            pending_migrations(&[1, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]),
+            vec![Migration::NormalizeTimestamps]
+        );
+        assert!(pending_migrations(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]).is_empty());
+    }
+}
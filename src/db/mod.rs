@@ -1,6 +1,12 @@
 pub mod entities;
 pub mod items;
+pub mod migrations;
 pub mod model;
 pub mod mongo;
+pub mod pipeline;
+pub mod search;
+pub mod secrets;
+pub mod subscriptions;
+pub mod tags;
 // pub mod refresh;
 pub mod channel;
@@ -9,7 +9,11 @@ use mongodb::{
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{collections::HashMap, fmt::{Debug, Display}, sync::Arc, vec};
 
-use super::mongo::{db_not_found_err, to_bson_vec, Handle};
+use super::{
+    mongo::{db_not_found_err, Handle},
+    pipeline::{Accumulator, FieldMatcher, Pipe, Pipeline, Projection},
+    search::{SearchQuery, Scored},
+};
 
 #[derive(Copy, Clone, Debug)]
 pub enum SortOrder {
@@ -72,6 +76,31 @@ pub trait CollectionModel<P: PartialEq, T: CollectionModelConstraint<P>> {
         Some(results)
     }
 
+    /// search runs a full-text `query` and returns matching documents, most relevant first.
+    /// The default implementation relies on a mongo text index,
+    /// other backends can override it with their own search engine.
+    async fn search(&self, query: &SearchQuery) -> Option<Vec<Scored<T>>> {
+        let mut cursor = self.collection()
+            .aggregate(query.to_pipeline(), None)
+            .await
+            .map_err(|err| {
+                eprintln!( "model::CollectionModel::search could not search: {}", err);
+                err
+            })
+            .ok()?;
+        let mut results = Vec::<Scored<T>>::new();
+        while let Some(doc) = cursor.next().await {
+            match doc {
+                Ok(doc) => match mongodb::bson::from_document::<Scored<T>>(doc) {
+                    Ok(t) => results.push(t),
+                    Err(e) => eprintln!("model::CollectionModel::search failed to deserialize document: {}", e),
+                },
+                Err(e) => eprintln!("model::CollectionModel::search failed to retrieve document: {}", e),
+            }
+        }
+        Some(results)
+    }
+
     // async fn update_one(&self, updates: &[(&str, )])
 
     /// insert_many inserts an array of documents into the collection
@@ -86,14 +115,22 @@ pub trait CollectionModel<P: PartialEq, T: CollectionModelConstraint<P>> {
             .map_err(Error::from)
     }
 
-    /// find_by_field_values fetch a `limit` number of documents matching a `field`
-    async fn find_by_field_values(&self, data: &[T], field: &str, limit: i64) -> Vec<T> {
+    /// find_by_field_values fetch a `limit` number of documents matching a `field`,
+    /// and the optional `filter`.
+    async fn find_by_field_values(
+        &self,
+        data: &[T],
+        field: &str,
+        limit: i64,
+        filter: impl Into<Option<Document>>,
+    ) -> Vec<T> {
         let mut in_values = vec![];
         for item in data {
             in_values.push(item.sort_by_value());
         }
 
-        let filter = doc! {field: { "$in": in_values }};
+        let mut filter = filter.into().unwrap_or_default();
+        filter.insert(field, doc! { "$in": in_values });
         let mut cursor = match self
             .collection()
             .find(
@@ -130,7 +167,7 @@ pub trait CollectionModel<P: PartialEq, T: CollectionModelConstraint<P>> {
         sort: impl Into<Option<(&str, SortOrder)>>,
         limit: impl Into<Option<i64>>,
     ) -> Option<Vec<T>> {
-        let sort_values = sort.into().unwrap_or_else(|| ("_id", SortOrder::DESC));
+        let sort_values = sort.into().unwrap_or(("_id", SortOrder::DESC));
         let find_options = FindOptions::builder()
             .limit(limit)
             .sort(doc! {
@@ -184,23 +221,24 @@ pub trait CollectionModel<P: PartialEq, T: CollectionModelConstraint<P>> {
             .max()
             .unwrap_or(max_limit);
         }
-        let mut pipeline = vec![
-            doc! { "$match": { field: { "$in": to_bson_vec(&field_in) } } },
-            doc! { "$group": {
-                "_id": format!("${}", field),
-                "docs": { "$push": "$$ROOT" }
-            }},
-            doc! { "$project": {
-                "_id": 0,
-                "link": 1,
-                "docs": { "$slice": ["$docs", max_limit * field_in.len() as i64] }
-            }}
+        let in_values: Vec<&i32> = field_in.iter().collect();
+        let sort_fields: Vec<(&str, SortOrder)> = sort_tuple.into().into_iter().collect();
+        let accumulators = [("docs", Accumulator::PushRoot)];
+        let projections = [
+            ("_id", Projection::Exclude),
+            ("link", Projection::Include),
+            ("docs", Projection::Slice("docs", max_limit * field_in.len() as i64)),
         ];
-        if let Some((field_name, order)) = sort_tuple.into() {
-            pipeline.insert(1, doc! { "$sort": {field_name: order.value()} });
+        let mut pipeline = Pipeline::new();
+        pipeline.push(Pipe::Match(field, FieldMatcher::In(&in_values)));
+        if !sort_fields.is_empty() {
+            pipeline.push(Pipe::Sort(&sort_fields));
         }
+        pipeline
+            .push(Pipe::Group(Some(field), &accumulators))
+            .push(Pipe::Project(&projections));
         let mut cursor = self.collection()
-            .aggregate(pipeline, None)
+            .aggregate(pipeline.build(), None)
             .await
             .map_err(|err| {
                 eprintln!( "model::CollectionModel::find_with_limits could not find latest: {}", err);
@@ -250,10 +288,9 @@ pub trait CollectionModel<P: PartialEq, T: CollectionModelConstraint<P>> {
             Some(d) => d,
             None => doc!{},
         };
-        let after_into = after.into();
-        if after_into.is_some() {
+        if let Some(after_into) = after.into() {
             filter_options.insert(field, doc! {
-                "$gt": after_into.unwrap(),
+                "$gt": after_into,
             });
         }
 
@@ -278,16 +315,17 @@ pub trait CollectionModel<P: PartialEq, T: CollectionModelConstraint<P>> {
     fn collection(&self) -> &Collection<T>;
     fn get_collection_name(&self) -> String;
     fn get_database(&self) -> Option<&Database>;
+    fn get_counters_collection_name(&self) -> String;
     fn get_diff_collection<C>(&self, coll: &str) -> Option<Collection<C>> {
         Some(self.get_database()?.collection::<C>(coll))
     }
-    /// get_next_seq requires a "counters" collection to exist, or writing rights to create it.
+    /// get_next_seq requires a counters collection (see `get_counters_collection_name`) to exist, or writing rights to create it.
     /// It will then try to fetch a document, containing a `seq` field, matching the collection's name as its `_id`.
     /// If does not exist, a new document with the previously mentioned specifics will be created, setting `seq` to `0`.
     /// Then, `seq` will be incremented by 1, and the document updated in the collection.
     /// Finally, the updated `seq` will be returned.
     async fn get_next_seq(&self) -> mongodb::error::Result<i32> {
-        let counters = self.get_diff_collection::<Counter>("counters")
+        let counters = self.get_diff_collection::<Counter>(&self.get_counters_collection_name())
             .ok_or(db_not_found_err())?;
         let filter = doc! { "_id": self.get_collection_name() };
         let update = doc! {
@@ -302,13 +340,13 @@ pub trait CollectionModel<P: PartialEq, T: CollectionModelConstraint<P>> {
         let result_doc = counters
             .find_one_and_update(filter, update, options)
             .await?
-            .ok_or_else(|| db_not_found_err());
+            .ok_or_else(db_not_found_err);
 
         result_doc.map(|doc| doc.seq)
     }
 
     async fn get_seq(&self, id: &str) -> mongodb::error::Result<i32> {
-        let counters = self.get_diff_collection::<Counter>("counters")
+        let counters = self.get_diff_collection::<Counter>(&self.get_counters_collection_name())
             .ok_or(db_not_found_err())?;
         let res = counters
             .find_one(doc! {"_id": id}, None)
@@ -346,13 +384,17 @@ impl<P: PartialEq, T: CollectionModelConstraint<P>> CollectionModel<P, T> for Bl
     fn get_database(&self) -> Option<&Database> {
         self.handle.database(&self.db_name)
     }
+
+    fn get_counters_collection_name(&self) -> String {
+        self.handle.counters_collection_name().to_string()
+    }
 }
 
 impl<T: CollectionModelConstraint<String>> BlankCollection<T> {
     pub fn new(handle: Arc<Handle>, db_name: &str, collection_name: &str) -> Result<Self, Error> {
         let collection = (match handle.database(db_name) {
             Some(res) => res,
-            None => return Error::to_result_string("no database found"),
+            None => return Err(Error(format!("no database found: {}", db_name))),
         })
         .collection::<T>(collection_name);
 
@@ -392,7 +434,7 @@ mod tests {
     async fn test_get_seq() {
         let settings = config::Settings::new().unwrap();
         let db_handle = Arc::new(db::mongo::get_handle(&settings).await);
-        let coll = BlankCollection::<Test>::new(db_handle, "panya", "test").unwrap();
+        let coll = BlankCollection::<Test>::new(db_handle, &settings.database, "test").unwrap();
         // coll.insert_many(&[Test{}]).await.unwrap();
 
         println!("next sequence: {}", coll.get_next_seq().await.unwrap() );
@@ -402,7 +444,7 @@ mod tests {
     async fn test_find_with_limits() {
         let settings = config::Settings::new().unwrap();
         let db_handle = Arc::new(db::mongo::get_handle(&settings).await);
-        let coll = Items::<PotentialArticle>::new(db_handle, "panya").unwrap();
+        let coll = Items::<PotentialArticle>::new(db_handle, &settings.database, &settings.collections.items).unwrap();
         // coll.insert_many(&[Test{}]).await.unwrap();
 
         println!("next find_with_limits: {:?}", coll.find_with_limits(
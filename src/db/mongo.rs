@@ -6,6 +6,7 @@ use crate::config::Settings;
 pub struct Handle {
     client: Client,
     databases: HashMap<String, Database>,
+    counters: String,
 }
 
 impl Handle {
@@ -17,6 +18,11 @@ impl Handle {
         self.databases.get(db_name)
     }
 
+    /// counters_collection_name is the collection used for sequences, see `CollectionModel::get_next_seq`
+    pub fn counters_collection_name(&self) -> &str {
+        &self.counters
+    }
+
 
     pub async fn new(settings: &Settings) -> Self {
         let mut client_options = ClientOptions::parse(&settings.db_path).await.unwrap();
@@ -28,12 +34,16 @@ impl Handle {
             .map(|name| (name.clone(), client.database(name)))
             .collect();
 
-        Handle { client, databases }
+        Handle {
+            client,
+            databases,
+            counters: settings.collections.counters.clone(),
+        }
     }
     
 }
 
-pub fn to_bson_vec(vec: &Vec<i32>) -> Vec<Bson> {
+pub fn to_bson_vec(vec: &[i32]) -> Vec<Bson> {
     vec.iter().map(|&id| Bson::from(id)).collect::<Vec<Bson>>()
 }
 
@@ -51,6 +61,6 @@ mod tests {
 
     #[test]
     fn test_to_bson_vec() {
-        assert_eq!(to_bson_vec(&vec![1, 2]), vec![Bson::Int32(1), Bson::Int32(2)]);
+        assert_eq!(to_bson_vec(&[1, 2]), vec![Bson::Int32(1), Bson::Int32(2)]);
     }
 }
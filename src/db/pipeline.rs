@@ -1,28 +1,189 @@
-use mongodb::bson::{doc, to_bson, Document};
+use mongodb::bson::{doc, to_bson, Bson, Document};
 use serde::Serialize;
 
+use super::model::SortOrder;
+
 #[derive(Debug, Clone)]
 pub enum FieldMatcher<'a, T: ?Sized + Serialize> {
     Lt(&'a T),
+    Lte(&'a T),
+    Gt(&'a T),
+    Gte(&'a T),
+    Eq(&'a T),
+    In(&'a [&'a T]),
+    // pattern, options (e.g. "i")
+    Regex(&'a str, &'a str),
+    Exists(bool),
+    // null or missing
+    Null,
 }
 
 impl<'a, T: ?Sized + Serialize> FieldMatcher<'a, T> {
     pub fn setup(&self) -> Document {
         match self {
             FieldMatcher::Lt(data) => doc! {"$lt": to_bson(data).unwrap_or_default()},
+            FieldMatcher::Lte(data) => doc! {"$lte": to_bson(data).unwrap_or_default()},
+            FieldMatcher::Gt(data) => doc! {"$gt": to_bson(data).unwrap_or_default()},
+            FieldMatcher::Gte(data) => doc! {"$gte": to_bson(data).unwrap_or_default()},
+            FieldMatcher::Eq(data) => doc! {"$eq": to_bson(data).unwrap_or_default()},
+            FieldMatcher::In(data) => doc! {"$in": data
+                .iter()
+                .map(|d| to_bson(d).unwrap_or_default())
+                .collect::<Vec<Bson>>()},
+            FieldMatcher::Regex(pattern, options) => {
+                doc! {"$regex": pattern.to_string(), "$options": options.to_string()}
+            }
+            FieldMatcher::Exists(exists) => doc! {"$exists": exists},
+            FieldMatcher::Null => doc! {"$eq": Bson::Null},
+        }
+    }
+}
+
+/// Accumulator is an operator of a `$group` stage.
+/// Fields are given without their `$` prefix.
+#[derive(Debug, Clone)]
+pub enum Accumulator<'a> {
+    PushRoot,
+    Push(&'a str),
+    First(&'a str),
+    Last(&'a str),
+    Max(&'a str),
+    Min(&'a str),
+    Avg(&'a str),
+    Sum(&'a str),
+    Count,
+}
+
+impl<'a> Accumulator<'a> {
+    pub fn setup(&self) -> Document {
+        match self {
+            Accumulator::PushRoot => doc! {"$push": "$$ROOT"},
+            Accumulator::Push(field) => doc! {"$push": field_path(field)},
+            Accumulator::First(field) => doc! {"$first": field_path(field)},
+            Accumulator::Last(field) => doc! {"$last": field_path(field)},
+            Accumulator::Max(field) => doc! {"$max": field_path(field)},
+            Accumulator::Min(field) => doc! {"$min": field_path(field)},
+            Accumulator::Avg(field) => doc! {"$avg": field_path(field)},
+            Accumulator::Sum(field) => doc! {"$sum": field_path(field)},
+            Accumulator::Count => doc! {"$sum": 1},
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Projection<'a> {
+    Include,
+    Exclude,
+    // keeps the first n elements of an array field
+    Slice(&'a str, i64),
+}
+
+impl<'a> Projection<'a> {
+    pub fn setup(&self) -> Bson {
+        match self {
+            Projection::Include => Bson::Int32(1),
+            Projection::Exclude => Bson::Int32(0),
+            Projection::Slice(field, n) => Bson::Document(doc! {"$slice": [field_path(field), n]}),
         }
     }
 }
 
+fn field_path(field: &str) -> String {
+    "$".to_string() + field
+}
+
 #[derive(Debug, Clone)]
 pub enum Pipe<'a, T: ?Sized + Serialize> {
+    // adds a field summing other fields, and matches on it
     AddFields(&'a str, &'a [&'a str], FieldMatcher<'a, T>),
+    Match(&'a str, FieldMatcher<'a, T>),
+    // matches on an aggregation expression, where missing fields compare lower than any value
+    MatchExpr(Document),
+    Sort(&'a [(&'a str, SortOrder)]),
+    Limit(i64),
+    Skip(i64),
+    // group key field (None groups every document together), accumulated fields
+    Group(Option<&'a str>, &'a [(&'a str, Accumulator<'a>)]),
+    Project(&'a [(&'a str, Projection<'a>)]),
+    Lookup {
+        from: &'a str,
+        local_field: &'a str,
+        foreign_field: &'a str,
+        as_field: &'a str,
+    },
+    Facet(Vec<(&'a str, Pipeline<'a, T>)>),
+}
+
+impl<'a, T: ?Sized + Serialize> Pipe<'a, T> {
+    /// stages returns the mongo stages of a pipe, in order
+    pub fn stages(&self) -> Vec<Document> {
+        match self {
+            Pipe::AddFields(field_name, add_fields, match_field) => {
+                let af: Vec<String> = add_fields.iter().map(|f| field_path(f)).collect();
+                vec![
+                    doc! {"$addFields": {*field_name: {"$add": af}}},
+                    doc! {"$match": {*field_name: match_field.setup()}},
+                ]
+            }
+            Pipe::Match(field_name, match_field) => {
+                vec![doc! {"$match": {*field_name: match_field.setup()}}]
+            }
+            Pipe::MatchExpr(expression) => vec![doc! {"$match": {"$expr": expression.clone()}}],
+            Pipe::Sort(fields) => {
+                let mut sort = doc! {};
+                fields.iter().for_each(|(field, order)| {
+                    sort.insert(*field, order.value());
+                });
+                vec![doc! {"$sort": sort}]
+            }
+            Pipe::Limit(n) => vec![doc! {"$limit": n}],
+            Pipe::Skip(n) => vec![doc! {"$skip": n}],
+            Pipe::Group(key, accumulators) => {
+                let mut group = doc! {"_id": key.map(field_path)};
+                accumulators.iter().for_each(|(field, acc)| {
+                    group.insert(*field, acc.setup());
+                });
+                vec![doc! {"$group": group}]
+            }
+            Pipe::Project(fields) => {
+                let mut project = doc! {};
+                fields.iter().for_each(|(field, projection)| {
+                    project.insert(*field, projection.setup());
+                });
+                vec![doc! {"$project": project}]
+            }
+            Pipe::Lookup {
+                from,
+                local_field,
+                foreign_field,
+                as_field,
+            } => vec![doc! {"$lookup": {
+                "from": *from,
+                "localField": *local_field,
+                "foreignField": *foreign_field,
+                "as": *as_field,
+            }}],
+            Pipe::Facet(facets) => {
+                let mut facet = doc! {};
+                facets.iter().for_each(|(field, pipeline)| {
+                    facet.insert(*field, pipeline.build());
+                });
+                vec![doc! {"$facet": facet}]
+            }
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Pipeline<'a, T: ?Sized + Serialize>(Vec<Pipe<'a, T>>);
 
-impl<'a, T: ?Sized + Serialize + Clone> Pipeline<'a, T> {
+impl<'a, T: ?Sized + Serialize> Default for Pipeline<'a, T> {
+    fn default() -> Self {
+        Pipeline(vec![])
+    }
+}
+
+impl<'a, T: Serialize + Clone> Pipeline<'a, T> {
     pub fn from_slice(pipes: &[Pipe<'a, T>]) -> Self {
         Pipeline(pipes.to_vec())
     }
@@ -41,41 +202,31 @@ impl<'a, T: ?Sized + Serialize + Clone> Pipeline<'a, T> {
     }
 
     pub fn new() -> Self {
-        Pipeline(vec![])
+        Self::default()
     }
 
     pub fn push(&mut self, pipe: Pipe<'a, T>) -> &mut Self {
         self.0.push(pipe);
         self
     }
+}
 
+impl<'a, T: ?Sized + Serialize> Pipeline<'a, T> {
+    /// build returns the mongo stages of every pipe, preserving the order pipes were added in
     pub fn build(&self) -> Vec<Document> {
-        let mut add_ops = doc! {};
-        let mut match_fields = doc! {};
-        self.0.iter().for_each(|pipe| {
-            match pipe {
-                Pipe::AddFields(field_name, add_fields, match_field) => {
-                    let field_string = field_name.to_string();
-                    let af: Vec<String> = add_fields
-                        .to_vec()
-                        .iter()
-                        .map(|f| "$".to_string() + f)
-                        .collect();
-                    add_ops.insert(&field_string, doc! {"$add": af});
-                    match_fields.insert(&field_string, match_field.setup())
-                }
-            };
-        });
-        vec![doc! {"$addFields": add_ops}, doc! {"$match": match_fields}]
+        self.0.iter().flat_map(|pipe| pipe.stages()).collect()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use chrono::Utc;
-    use mongodb::bson::doc;
+    use mongodb::bson::{doc, Bson};
 
-    use crate::db::pipeline::{FieldMatcher, Pipeline};
+    use crate::db::{
+        model::SortOrder,
+        pipeline::{Accumulator, FieldMatcher, Pipeline, Projection},
+    };
 
     use super::Pipe;
 
@@ -102,4 +253,97 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_field_matchers() {
+        let one = Bson::Int32(1);
+        let two = Bson::Int32(2);
+        assert_eq!(FieldMatcher::Gt(&one).setup(), doc! {"$gt": 1});
+        assert_eq!(FieldMatcher::Gte(&one).setup(), doc! {"$gte": 1});
+        assert_eq!(FieldMatcher::Lte(&one).setup(), doc! {"$lte": 1});
+        assert_eq!(FieldMatcher::Eq(&one).setup(), doc! {"$eq": 1});
+        assert_eq!(
+            FieldMatcher::In(&[&one, &two]).setup(),
+            doc! {"$in": [1, 2]}
+        );
+        assert_eq!(
+            FieldMatcher::<Bson>::Regex("^nhk", "i").setup(),
+            doc! {"$regex": "^nhk", "$options": "i"}
+        );
+        assert_eq!(
+            FieldMatcher::<Bson>::Exists(false).setup(),
+            doc! {"$exists": false}
+        );
+    }
+
+    #[test]
+    fn test_pipeline_keeps_stage_order() {
+        let channel_ids = [&1, &2];
+        let mut pipeline = Pipeline::new();
+        pipeline
+            .push(Pipe::Match("channel_id", FieldMatcher::In(&channel_ids)))
+            .push(Pipe::Sort(&[("create_date", SortOrder::DESC)]))
+            .push(Pipe::Group(
+                Some("channel_id"),
+                &[("docs", Accumulator::PushRoot), ("total", Accumulator::Count)],
+            ))
+            .push(Pipe::Project(&[
+                ("_id", Projection::Exclude),
+                ("docs", Projection::Slice("docs", 5)),
+            ]))
+            .push(Pipe::Skip(1))
+            .push(Pipe::Limit(2));
+        assert_eq!(
+            pipeline.build(),
+            vec![
+                doc! {"$match": {"channel_id": {"$in": [1, 2]}}},
+                doc! {"$sort": {"create_date": -1}},
+                doc! {"$group": {
+                    "_id": "$channel_id",
+                    "docs": {"$push": "$$ROOT"},
+                    "total": {"$sum": 1},
+                }},
+                doc! {"$project": {"_id": 0, "docs": {"$slice": ["$docs", 5_i64]}}},
+                doc! {"$skip": 1_i64},
+                doc! {"$limit": 2_i64},
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lookup_and_facet() {
+        let mut latest = Pipeline::<i32>::new();
+        latest
+            .push(Pipe::Sort(&[("create_date", SortOrder::DESC)]))
+            .push(Pipe::Limit(3));
+        let mut per_channel = Pipeline::<i32>::new();
+        per_channel.push(Pipe::Group(
+            Some("channel_id"),
+            &[("count", Accumulator::Count)],
+        ));
+        assert_eq!(
+            Pipeline::from_slice(&[
+                Pipe::Lookup {
+                    from: "channels",
+                    local_field: "channel_id",
+                    foreign_field: "id",
+                    as_field: "channel",
+                },
+                Pipe::Facet(vec![("latest", latest), ("per_channel", per_channel)]),
+            ])
+            .build(),
+            vec![
+                doc! {"$lookup": {
+                    "from": "channels",
+                    "localField": "channel_id",
+                    "foreignField": "id",
+                    "as": "channel",
+                }},
+                doc! {"$facet": {
+                    "latest": [{"$sort": {"create_date": -1}}, {"$limit": 3_i64}],
+                    "per_channel": [{"$group": {"_id": "$channel_id", "count": {"$sum": 1}}}],
+                }},
+            ]
+        );
+    }
 }
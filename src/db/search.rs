@@ -0,0 +1,96 @@
+use mongodb::bson::{doc, Document};
+use serde::{Deserialize, Serialize};
+
+/// SearchQuery describes a full-text search over a collection.
+/// `filter` restricts the documents searched, on top of the text match.
+#[derive(Debug, Clone)]
+pub struct SearchQuery {
+    pub text: String,
+    pub filter: Document,
+    pub limit: i64,
+}
+
+impl SearchQuery {
+    pub fn new(text: &str, limit: i64) -> Self {
+        SearchQuery {
+            text: text.to_string(),
+            filter: doc! {},
+            limit,
+        }
+    }
+
+    pub fn filter_eq(mut self, field: &str, value: impl Into<mongodb::bson::Bson>) -> Self {
+        self.filter.insert(field, value.into());
+        self
+    }
+
+    pub fn filter_in(mut self, field: &str, values: &[i32]) -> Self {
+        if !values.is_empty() {
+            self.filter.insert(field, doc! {"$in": values});
+        }
+        self
+    }
+
+    /// filter_range keeps documents which `field` is within [`from`, `to`]
+    pub fn filter_range(mut self, field: &str, from: Option<i64>, to: Option<i64>) -> Self {
+        let mut range = doc! {};
+        if let Some(f) = from {
+            range.insert("$gte", f);
+        }
+        if let Some(t) = to {
+            range.insert("$lte", t);
+        }
+        if !range.is_empty() {
+            self.filter.insert(field, range);
+        }
+        self
+    }
+
+    /// to_pipeline builds the mongo aggregation pipeline of the query, relying on a text index.
+    /// Results are ordered by relevance, exposed through a `score` field.
+    pub fn to_pipeline(&self) -> Vec<Document> {
+        let mut matcher = self.filter.clone();
+        matcher.insert("$text", doc! {"$search": &self.text});
+        vec![
+            doc! {"$match": matcher},
+            doc! {"$addFields": {"score": {"$meta": "textScore"}}},
+            doc! {"$sort": {"score": {"$meta": "textScore"}}},
+            doc! {"$limit": self.limit},
+        ]
+    }
+}
+
+/// Scored wraps a document with its relevance score
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Scored<T> {
+    #[serde(flatten)]
+    pub doc: T,
+    pub score: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_query_pipeline() {
+        let query = SearchQuery::new("neurotech", 10)
+            .filter_eq("tenant", "default")
+            .filter_in("channel_id", &[1, 2])
+            .filter_range("create_date", Some(1000), None);
+        assert_eq!(
+            query.to_pipeline(),
+            vec![
+                doc! {"$match": {
+                    "tenant": "default",
+                    "channel_id": {"$in": [1, 2]},
+                    "create_date": {"$gte": 1000_i64},
+                    "$text": {"$search": "neurotech"},
+                }},
+                doc! {"$addFields": {"score": {"$meta": "textScore"}}},
+                doc! {"$sort": {"score": {"$meta": "textScore"}}},
+                doc! {"$limit": 10_i64},
+            ]
+        );
+    }
+}
@@ -0,0 +1,107 @@
+use super::{
+    model::{CollectionModel, CollectionModelConstraint},
+    mongo::Handle,
+};
+use crate::{
+    entities::subscription::{Subscription, SubscriptionStatus},
+    error::Error,
+};
+use mongodb::{
+    bson::{doc, to_bson},
+    options::ReplaceOptions,
+    Collection, Database,
+};
+use serde::Serialize;
+use std::{fmt::Debug, sync::Arc};
+
+#[derive(Debug)]
+pub struct Subscriptions<T: Serialize> {
+    collection: Collection<T>,
+    handle: Arc<Handle>,
+    db_name: String,
+}
+
+impl Subscriptions<Subscription> {
+    /// upsert creates or replaces the subscription of a channel
+    pub async fn upsert(&self, subscription: &Subscription) -> Result<(), Error> {
+        self.collection()
+            .replace_one(
+                doc! {"channel_id": subscription.channel_id},
+                subscription,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    pub async fn get(&self, channel_id: i32) -> Result<Option<Subscription>, Error> {
+        self.collection()
+            .find_one(doc! {"channel_id": channel_id}, None)
+            .await
+            .map_err(Error::from)
+    }
+
+    /// set_status records the hub's answer to a subscription request
+    pub async fn set_status(
+        &self,
+        channel_id: i32,
+        status: SubscriptionStatus,
+        expires_at: Option<i64>,
+        reason: Option<&str>,
+    ) -> Result<(), Error> {
+        let status = to_bson(&status).map_err(|err| Error(err.to_string()))?;
+        self.collection()
+            .update_one(
+                doc! {"channel_id": channel_id},
+                doc! {"$set": {"status": status, "expires_at": expires_at, "reason": reason}},
+                None,
+            )
+            .await
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    pub async fn set_last_push(&self, channel_id: i32, date: i64) -> Result<(), Error> {
+        self.collection()
+            .update_one(
+                doc! {"channel_id": channel_id},
+                doc! {"$set": {"last_push": date}},
+                None,
+            )
+            .await
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    pub fn new(handle: Arc<Handle>, db_name: &str, collection_name: &str) -> Result<Self, Error> {
+        let collection = (match handle.database(db_name) {
+            Some(res) => res,
+            None => return Err(Error(format!("no database found: {}", db_name))),
+        })
+        .collection::<Subscription>(collection_name);
+        Ok(Subscriptions {
+            db_name: db_name.to_string(),
+            handle,
+            collection,
+        })
+    }
+}
+
+impl<P: PartialEq, T: CollectionModelConstraint<P>> CollectionModel<P, T> for Subscriptions<T> {
+    fn collection(&self) -> &Collection<T> {
+        &self.collection
+    }
+
+    fn get_collection_name(&self) -> String {
+        self.collection.name().to_string()
+    }
+
+    fn get_database(&self) -> Option<&Database> {
+        self.handle.database(&self.db_name)
+    }
+
+    fn get_counters_collection_name(&self) -> String {
+        self.handle.counters_collection_name().to_string()
+    }
+}
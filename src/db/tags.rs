@@ -0,0 +1,70 @@
+use super::{
+    model::{CollectionModel, CollectionModelConstraint},
+    mongo::Handle,
+};
+use crate::{entities::tag::Tag, error::Error};
+use mongodb::{bson::doc, options::ReplaceOptions, Collection, Database};
+use serde::Serialize;
+use std::{fmt::Debug, sync::Arc};
+
+#[derive(Debug)]
+pub struct Tags<T: Serialize> {
+    collection: Collection<T>,
+    handle: Arc<Handle>,
+    db_name: String,
+}
+
+impl Tags<Tag> {
+    /// upsert creates or replaces the tag of a tenant with the same name
+    pub async fn upsert(&self, tag: &Tag) -> Result<(), Error> {
+        self.collection()
+            .replace_one(
+                doc! {"tenant": &tag.tenant, "name": &tag.name},
+                tag,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    /// delete removes a tag, returning whether it existed
+    pub async fn delete(&self, tenant: &str, name: &str) -> Result<bool, Error> {
+        self.collection()
+            .delete_one(doc! {"tenant": tenant, "name": name}, None)
+            .await
+            .map(|res| res.deleted_count > 0)
+            .map_err(Error::from)
+    }
+
+    pub fn new(handle: Arc<Handle>, db_name: &str, collection_name: &str) -> Result<Self, Error> {
+        let collection = (match handle.database(db_name) {
+            Some(res) => res,
+            None => return Err(Error(format!("no database found: {}", db_name))),
+        })
+        .collection::<Tag>(collection_name);
+        Ok(Tags {
+            db_name: db_name.to_string(),
+            handle,
+            collection,
+        })
+    }
+}
+
+impl<P: PartialEq, T: CollectionModelConstraint<P>> CollectionModel<P, T> for Tags<T> {
+    fn collection(&self) -> &Collection<T> {
+        &self.collection
+    }
+
+    fn get_collection_name(&self) -> String {
+        self.collection.name().to_string()
+    }
+
+    fn get_database(&self) -> Option<&Database> {
+        self.handle.database(&self.db_name)
+    }
+
+    fn get_counters_collection_name(&self) -> String {
+        self.handle.counters_collection_name().to_string()
+    }
+}
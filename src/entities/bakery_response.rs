@@ -0,0 +1,30 @@
+use serde::{Deserialize, Serialize};
+
+use super::potential_articles::PotentialArticle;
+
+/// Latest version of the bakery response envelope patishie understands
+pub const BAKERY_RESPONSE_VERSION: u32 = 1;
+
+/// ScrapeMetadata describes how bakery scraped a page
+#[derive(Debug, Clone, Default, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct ScrapeMetadata {
+    // e.g. "ok", "partial", "blocked"
+    pub status: Option<String>,
+    // css selector the articles were found with
+    pub selector: Option<String>,
+    #[serde(default)]
+    pub warnings: Vec<String>,
+    pub page_title: Option<String>,
+}
+
+/// BakeryResponse is the versioned envelope of bakery's answers:
+/// `{"version": 1, "articles": [...], "metadata": {...}}`.
+/// Older bakeries answer a bare array of articles, read as a response without metadata.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BakeryResponse {
+    pub version: u32,
+    #[serde(default)]
+    pub articles: Vec<PotentialArticle>,
+    #[serde(default)]
+    pub metadata: ScrapeMetadata,
+}
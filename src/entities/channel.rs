@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
 use crate::{
@@ -8,11 +10,31 @@ use crate::{
     error::Error,
 };
 
-use super::source_type::SourceType;
+use super::{
+    filter_rule::FilterRule,
+    image_source::{ImageSource, DEFAULT_IMAGE_SOURCES},
+    redirect::{PendingRedirect, UrlChange},
+    refresh_hints::RefreshHints,
+    refresh_report::RefreshReport,
+    source_type::SourceType,
+    text_normalization::TextNormalization,
+};
+
+/// Value shown in place of a channel's header values
+pub const REDACTED: &str = "[redacted]";
+
+/// Tenant given to documents created before tenants existed
+pub const DEFAULT_TENANT: &str = "default";
+
+pub fn default_tenant() -> String {
+    DEFAULT_TENANT.to_string()
+}
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, PartialOrd)]
 pub struct Channel {
     pub id: i32,
+    #[serde(default = "default_tenant")]
+    pub tenant: String,
     pub name: String,
     pub url: String,
     pub last_refresh: i64,
@@ -21,6 +43,30 @@ pub struct Channel {
     pub base_refresh_frequency: Option<i32>,
     pub source_type: SourceType,
     pub weight: f32,
+    // overrides the order in which image sources of rss items are tried
+    pub image_sources: Option<Vec<ImageSource>>,
+    // include/exclude rules applied to new items, see `services::filter`
+    pub filters: Option<Vec<FilterRule>>,
+    pub last_refresh_report: Option<RefreshReport>,
+    // tags given to every item of the channel
+    pub default_tags: Option<Vec<String>>,
+    // language declared by the channel's feed, also given to items which language is unknown
+    pub language: Option<String>,
+    // ruby and width normalization of items' texts, disabled when unset
+    pub text_normalization: Option<TextNormalization>,
+    // extra request headers sent when fetching the channel's url, e.g. cookies or authorization
+    pub headers: Option<BTreeMap<String, String>>,
+    // name of the tenant's credential used to fetch the channel, see `entities::credential`
+    pub credentials: Option<String>,
+    // previous urls of the channel, oldest first
+    pub url_history: Option<Vec<UrlChange>>,
+    pub pending_redirect: Option<PendingRedirect>,
+    // date the channel was disabled, e.g. when its url answered 410 Gone; disabled channels are not refreshed
+    pub disabled_at: Option<i64>,
+    // what the feed's publisher last told about its refreshes, see `services::schedule`
+    pub refresh_hints: Option<RefreshHints>,
+    // minimum interval (ms) between refreshes, replacing the one derived from `refresh_hints`
+    pub min_refresh_interval: Option<i64>,
 }
 
 impl PrimaryID<i32> for Channel {
@@ -36,9 +82,38 @@ impl FieldSort<String> for Channel {
 }
 
 impl Channel {
-    pub fn new(name: &str, url: &str, source: SourceType) -> Self {
+    pub fn get_image_sources(&self) -> &[ImageSource] {
+        self.image_sources
+            .as_deref()
+            .unwrap_or(DEFAULT_IMAGE_SOURCES)
+    }
+
+    pub fn get_filters(&self) -> &[FilterRule] {
+        self.filters.as_deref().unwrap_or_default()
+    }
+
+    pub fn get_default_tags(&self) -> &[String] {
+        self.default_tags.as_deref().unwrap_or_default()
+    }
+
+    /// has_secrets tells whether fetching the channel sends headers or a credential
+    pub fn has_secrets(&self) -> bool {
+        self.headers.as_ref().is_some_and(|headers| !headers.is_empty()) || self.credentials.is_some()
+    }
+
+    /// redacted hides the values of the channel's `headers`, e.g. cookies or tokens,
+    /// keeping their names, for the channel to be shown by the API
+    pub fn redacted(mut self) -> Self {
+        if let Some(headers) = self.headers.as_mut() {
+            headers.values_mut().for_each(|value| *value = REDACTED.to_string());
+        }
+        self
+    }
+
+    pub fn new(tenant: &str, name: &str, url: &str, source: SourceType) -> Self {
         Channel {
             id: 0,
+            tenant: tenant.to_string(),
             name: name.to_string(),
             url: url.to_string(),
             last_refresh: 0,
@@ -47,17 +122,31 @@ impl Channel {
             base_refresh_frequency: Some(60000),
             source_type: source,
             weight: 1.,
+            image_sources: None,
+            filters: None,
+            last_refresh_report: None,
+            default_tags: None,
+            language: None,
+            text_normalization: None,
+            headers: None,
+            credentials: None,
+            url_history: None,
+            pending_redirect: None,
+            disabled_at: None,
+            refresh_hints: None,
+            min_refresh_interval: None,
         }
     }
 }
 
 pub async fn new_with_seq_db(
+    tenant: &str,
     name: &str,
     url: &str,
     source: SourceType,
     channels_coll: &Channels<Channel>,
 ) -> Result<Channel, Error> {
-    let mut channel = Channel::new(name, url, source);
+    let mut channel = Channel::new(tenant, name, url, source);
     channel.id = channels_coll.get_next_seq().await?;
     channels_coll
         .insert_many(&[channel.clone()])
@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+
+use crate::db::model::{FieldSort, PrimaryID};
+
+use super::channel::default_tenant;
+
+/// CredentialAuth is how a credential authenticates requests.
+/// Secret values prefixed with `env:` or `file:` are read from an environment variable
+/// or a file when used, see `services::credentials::resolve_secret`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CredentialAuth {
+    Basic { username: String, password: String },
+    Bearer { token: String },
+    // key sent as the query parameter `param`
+    Query { param: String, value: String },
+}
+
+impl CredentialAuth {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CredentialAuth::Basic { .. } => "basic",
+            CredentialAuth::Bearer { .. } => "bearer",
+            CredentialAuth::Query { .. } => "query",
+        }
+    }
+}
+
+/// Credential is a named secret of a tenant, referenced by channels' `credentials`.
+/// Credentials are kept in their own collection, so that listing channels never exposes them.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Credential {
+    #[serde(default = "default_tenant")]
+    pub tenant: String,
+    pub name: String,
+    pub auth: CredentialAuth,
+}
+
+/// CredentialSummary is what the API tells about a credential, without its secret values
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct CredentialSummary {
+    pub name: String,
+    pub kind: String,
+}
+
+impl From<&Credential> for CredentialSummary {
+    fn from(credential: &Credential) -> Self {
+        CredentialSummary {
+            name: credential.name.clone(),
+            kind: credential.auth.kind().to_string(),
+        }
+    }
+}
+
+impl PrimaryID<String> for Credential {
+    fn get_primary_id(&self) -> Option<String> {
+        Some(self.name.clone())
+    }
+}
+
+impl FieldSort<String> for Credential {
+    fn sort_by_value(&self) -> String {
+        self.name.clone()
+    }
+}
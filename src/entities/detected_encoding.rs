@@ -0,0 +1,22 @@
+use serde::{Deserialize, Serialize};
+
+/// CharsetSource tells where the encoding of a fetched body was found
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CharsetSource {
+    Bom,
+    Header,
+    // the encoding of an <?xml ?> declaration
+    Declaration,
+    // guessed from the body's bytes
+    Detected,
+}
+
+/// DetectedEncoding is the encoding a body was transcoded to utf-8 from
+#[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct DetectedEncoding {
+    pub name: String,
+    pub source: CharsetSource,
+    // whether invalid sequences were replaced with U+FFFD
+    pub lossy: bool,
+}
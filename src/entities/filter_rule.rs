@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterAction {
+    Include,
+    Exclude,
+}
+
+/// FilterField is the part of an item a rule's pattern is matched against
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterField {
+    Title,
+    Description,
+    Link,
+    // matches if any of the item's categories does
+    Category,
+}
+
+/// FilterRule is a per-channel rule, see `services::filter` for how rules combine.
+#[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct FilterRule {
+    pub action: FilterAction,
+    pub field: FilterField,
+    // regex, use (?i) for case insensitive matches
+    pub pattern: String,
+}
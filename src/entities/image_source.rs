@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+
+/// ImageSource is a place of an rss item where its image can be found.
+/// A channel can override the order sources are tried in, see `Channel::image_sources`.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageSource {
+    // <media:content>
+    MediaContent,
+    // <media:content> of a <media:group>
+    MediaGroup,
+    // <media:thumbnail>
+    MediaThumbnail,
+    // <enclosure type="image/*">
+    Enclosure,
+    // <itunes:image href="">
+    ItunesImage,
+    // first <img> of <description>
+    Description,
+    // first <img> of <content:encoded>
+    ContentEncoded,
+}
+
+pub const DEFAULT_IMAGE_SOURCES: &[ImageSource] = &[
+    ImageSource::MediaContent,
+    ImageSource::MediaGroup,
+    ImageSource::MediaThumbnail,
+    ImageSource::Enclosure,
+    ImageSource::ItunesImage,
+    ImageSource::ContentEncoded,
+    ImageSource::Description,
+];
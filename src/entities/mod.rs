@@ -1,4 +1,15 @@
 pub mod potential_articles;
+pub mod bakery_response;
 pub mod channel;
+pub mod credential;
+pub mod detected_encoding;
+pub mod filter_rule;
+pub mod image_source;
+pub mod redirect;
+pub mod refresh_hints;
+pub mod refresh_report;
 pub mod source_type;
-pub mod rss;
\ No newline at end of file
+pub mod subscription;
+pub mod tag;
+pub mod text_normalization;
+pub mod rss;
@@ -3,17 +3,76 @@ use serde::{Deserialize, Serialize};
 
 use crate::db::model::{FieldSort, PrimaryID};
 
-#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq, PartialOrd)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, Eq, PartialEq)]
+pub struct ArticleEnclosure {
+    pub url: String,
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    // in bytes
+    pub length: Option<i64>,
+}
+
+/// RubyAnnotation is the reading of an annotated text, e.g. 東京 read とうきょう
+#[derive(Clone, Debug, Default, Serialize, Deserialize, Eq, PartialEq)]
+pub struct RubyAnnotation {
+    pub base: String,
+    pub reading: String,
+}
+
+/// Podcast gathers the itunes:* tags of a podcast episode
+#[derive(Clone, Debug, Default, Serialize, Deserialize, Eq, PartialEq)]
+pub struct Podcast {
+    // in seconds
+    pub duration: Option<i64>,
+    pub episode: Option<i32>,
+    pub season: Option<i32>,
+    pub explicit: Option<bool>,
+    pub image: Option<String>,
+    // first audio enclosure
+    pub audio: Option<ArticleEnclosure>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, Eq, PartialEq)]
 pub struct PotentialArticle {
     pub link: String,
+    // deduplication key, see `services::link::canonical_url`
+    pub canonical_link: Option<String>,
+    // canonical form of the feed's link, kept when `canonical_link` is replaced by the page's own
+    pub link_key: Option<String>,
     pub img: String,
     pub desc: String,
+    // sanitized version of `desc`
+    pub desc_html: Option<String>,
+    // plain text version of `desc`, truncated
+    pub excerpt: Option<String>,
     pub title: Option<String>,
     #[serde(alias = "date")]
     pub create_date: i64,
     pub channel_name: Option<String>,
     pub channel_id: Option<i32>,
+    pub tenant: Option<String>,
     pub categories: Option<Vec<String>>,
+    // canonical tags of the tenant's taxonomy, see `services::taxonomy`
+    pub tags: Option<Vec<String>>,
+    // ISO 639-1 code when it exists, declared by the feed or detected, see `services::language`
+    pub language: Option<String>,
+    // plain text of the description without ruby annotations, see `services::japanese`
+    pub reading: Option<String>,
+    pub ruby: Option<Vec<RubyAnnotation>>,
+    // simhash of title and description, see `services::duplicate`
+    pub fingerprint: Option<i64>,
+    // canonical link of the first item of the near-duplicates cluster
+    pub cluster_id: Option<String>,
+    // full content (<content:encoded>), sanitized
+    pub content: Option<String>,
+    pub authors: Option<Vec<String>>,
+    pub guid: Option<String>,
+    // a permalink guid is an alternate deduplication key
+    pub guid_is_permalink: Option<bool>,
+    pub enclosures: Option<Vec<ArticleEnclosure>>,
+    pub comments: Option<String>,
+    pub update_date: Option<i64>,
+    pub podcast: Option<Podcast>,
 }
 
 impl PotentialArticle {
@@ -34,7 +93,7 @@ impl PotentialArticle {
     }
 
     pub fn human_date(&self) -> String {
-        Utc.timestamp_opt(self.create_date, 0)
+        Utc.timestamp_millis_opt(self.create_date)
             .single()
             .unwrap_or(Utc::now())
             .format("%Y-%m-%d %H:%M:%S")
@@ -44,6 +103,22 @@ impl PotentialArticle {
     pub fn some_human_date(&self) -> Option<String> {
         Some(self.human_date())
     }
+
+    /// permalink_guid returns the guid of the article when it is a permalink
+    pub fn permalink_guid(&self) -> Option<&str> {
+        match self.guid_is_permalink {
+            Some(true) => self.guid.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// cluster_key identifies the cluster an article belongs to,
+    /// or the cluster it would be the canonical item of.
+    pub fn cluster_key(&self) -> String {
+        self.cluster_id
+            .clone()
+            .unwrap_or_else(|| self.sort_by_value())
+    }
 }
 
 impl Ord for PotentialArticle {
@@ -52,6 +127,12 @@ impl Ord for PotentialArticle {
     }
 }
 
+impl PartialOrd for PotentialArticle {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl PrimaryID<i32> for PotentialArticle {
     fn get_primary_id(&self) -> Option<i32> {
         self.channel_id
@@ -60,6 +141,6 @@ impl PrimaryID<i32> for PotentialArticle {
 
 impl FieldSort<String> for PotentialArticle {
     fn sort_by_value(&self) -> String {
-        self.link.clone()
+        self.canonical_link.clone().unwrap_or_else(|| self.link.clone())
     }
 }
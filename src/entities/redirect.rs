@@ -0,0 +1,18 @@
+use serde::{Deserialize, Serialize};
+
+/// UrlChange is a previous url of a channel
+#[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct UrlChange {
+    pub url: String,
+    // date the channel left this url
+    pub date: i64,
+}
+
+/// PendingRedirect is a permanent redirect of a channel's url seen on its last refreshes,
+/// applied once seen `count` times in a row
+#[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct PendingRedirect {
+    pub target: String,
+    pub count: u32,
+    pub first_seen: i64,
+}
@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+
+/// RefreshHints are what a publisher tells about how often its feed may be fetched.
+/// Intervals are in milliseconds.
+#[derive(Debug, Clone, Default, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct RefreshHints {
+    // <ttl>
+    pub ttl: Option<i64>,
+    // <sy:updatePeriod> divided by <sy:updateFrequency>
+    pub syndication: Option<i64>,
+    // Cache-Control: max-age
+    pub max_age: Option<i64>,
+    // Retry-After
+    pub retry_after: Option<i64>,
+    // hours (0 to 23, GMT) and days (e.g. "Monday") during which the feed should not be fetched
+    #[serde(default)]
+    pub skip_hours: Vec<u32>,
+    #[serde(default)]
+    pub skip_days: Vec<String>,
+}
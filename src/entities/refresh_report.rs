@@ -0,0 +1,21 @@
+use serde::{Deserialize, Serialize};
+
+use super::{bakery_response::ScrapeMetadata, detected_encoding::DetectedEncoding};
+
+/// RefreshReport sums up the last refresh of a channel
+#[derive(Debug, Clone, Default, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct RefreshReport {
+    pub date: i64,
+    pub fetched: i64,
+    pub inserted: i64,
+    // items dropped by the channel's filter rules
+    pub filtered: i64,
+    // why a refresh returned nothing, or went wrong
+    #[serde(default)]
+    pub warnings: Vec<String>,
+    // how bakery scraped the page of a bakery channel
+    pub scrape: Option<ScrapeMetadata>,
+    // the encoding the feed of an rss channel was transcoded from
+    #[serde(default)]
+    pub encoding: Option<DetectedEncoding>,
+}
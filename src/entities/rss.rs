@@ -1,11 +1,97 @@
 use chrono::{DateTime, ParseError, Utc};
 use serde::{Deserialize, Serialize};
+use url::Url;
 
+use crate::services::{
+    content::{attribute, tag_attributes},
+    schedule::syndication_interval,
+};
+
+use super::{
+    image_source::ImageSource,
+    potential_articles::{ArticleEnclosure, Podcast},
+    refresh_hints::RefreshHints,
+};
+
+/// Content is a <media:content> or a <media:thumbnail>
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct Content {
     pub url: String,
     pub description: Option<String>,
     pub credit: Option<String>,
+    pub width: Option<String>,
+    pub height: Option<String>,
+    pub medium: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+}
+
+impl Content {
+    fn is_image(&self) -> bool {
+        self.medium.as_deref().map(|m| m == "image").unwrap_or(true)
+            && self
+                .kind
+                .as_deref()
+                .map(|k| k.starts_with("image/"))
+                .unwrap_or(true)
+    }
+
+    fn area(&self) -> u64 {
+        let dimension = |d: &Option<String>| {
+            d.as_deref()
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .unwrap_or(0)
+        };
+        dimension(&self.width) * dimension(&self.height)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct MediaGroup {
+    #[serde(default)]
+    pub content: Vec<Content>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Enclosure {
+    pub url: String,
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    pub length: Option<String>,
+}
+
+impl Enclosure {
+    pub fn to_article_enclosure(&self) -> ArticleEnclosure {
+        ArticleEnclosure {
+            url: self.url.trim().to_string(),
+            kind: self.kind.clone(),
+            length: self.length.as_deref().and_then(|l| l.trim().parse::<i64>().ok()),
+        }
+    }
+}
+
+/// Guid is a <guid isPermaLink="">, isPermaLink defaults to true
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Guid {
+    #[serde(rename = "isPermaLink")]
+    pub is_perma_link: Option<String>,
+    #[serde(rename = "$value")]
+    pub value: Option<String>,
+}
+
+impl Guid {
+    pub fn is_perma_link(&self) -> bool {
+        self.is_perma_link
+            .as_deref()
+            .map(|p| !p.trim().eq_ignore_ascii_case("false"))
+            .unwrap_or(true)
+    }
+}
+
+/// ItunesImage is an <itunes:image href="">
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct ItunesImage {
+    pub href: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
@@ -14,10 +100,66 @@ pub struct Item {
     #[serde(alias = "pubDate")]
     pub pub_date: Option<String>,
     pub description: Option<String>,
-    pub creator: Option<String>,
+    // <dc:creator>
+    #[serde(default)]
+    pub creator: Vec<String>,
+    #[serde(default)]
+    pub author: Vec<String>,
     pub category: Option<Vec<String>>,
     pub link: Option<String>,
-    pub content: Option<Content>,
+    // <media:content>
+    #[serde(default)]
+    pub content: Vec<Content>,
+    // <media:thumbnail>
+    #[serde(default)]
+    pub thumbnail: Vec<Content>,
+    // <media:group>
+    pub group: Option<MediaGroup>,
+    #[serde(default)]
+    pub enclosure: Vec<Enclosure>,
+    // <itunes:image>
+    pub image: Option<ItunesImage>,
+    // <content:encoded>
+    pub encoded: Option<String>,
+    pub guid: Option<Guid>,
+    pub comments: Option<String>,
+    // <itunes:duration>, <itunes:episode>, <itunes:season>, <itunes:explicit>
+    pub duration: Option<String>,
+    pub episode: Option<String>,
+    pub season: Option<String>,
+    pub explicit: Option<String>,
+    // <dc:language>
+    pub language: Option<String>,
+    // <atom:updated> or <dc:modified>
+    #[serde(alias = "modified")]
+    pub updated: Option<String>,
+}
+
+/// parse_duration reads an <itunes:duration>, given in seconds, MM:SS or HH:MM:SS.
+/// Durations which do not fit an i64 are ignored.
+fn parse_duration(duration: &str) -> Option<i64> {
+    duration
+        .trim()
+        .split(':')
+        .try_fold((0i64, 0usize), |(total, parts), part| {
+            let value = part
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|v| *v >= 0.0 && *v < i64::MAX as f64)?;
+            let total = total.checked_mul(60)?.checked_add(value as i64)?;
+            Some((total, parts + 1))
+        })
+        .filter(|(_, parts)| *parts <= 3)
+        .map(|(total, _)| total)
+}
+
+fn parse_explicit(explicit: &str) -> Option<bool> {
+    match explicit.trim().to_lowercase().as_str() {
+        "yes" | "true" | "explicit" => Some(true),
+        "no" | "false" | "clean" => Some(false),
+        _ => None,
+    }
 }
 
 fn parse_date(date_str: &str) -> Result<DateTime<Utc>, ParseError> {
@@ -43,12 +185,128 @@ impl Item {
             .unwrap_or(default_date)
             .timestamp_millis()
     }
-    pub fn get_img(&self) -> String {
-        self.content
-            .as_ref()
-            .map(|c| c.url.clone())
+    /// get_img returns the image of the item found in the first of the `sources` declaring one.
+    /// Among the images of a same source, the largest declared one is preferred.
+    pub fn get_img(&self, sources: &[ImageSource]) -> String {
+        sources
+            .iter()
+            .find_map(|source| self.get_img_from(*source))
             .unwrap_or_default()
     }
+
+    fn get_img_from(&self, source: ImageSource) -> Option<String> {
+        let largest = |contents: &[Content]| {
+            contents
+                .iter()
+                .filter(|c| c.is_image() && !c.url.is_empty())
+                // max_by_key returns the last max element, rev() keeps the first one
+                .rev()
+                .max_by_key(|c| c.area())
+                .map(|c| c.url.clone())
+        };
+        match source {
+            ImageSource::MediaContent => largest(&self.content),
+            ImageSource::MediaGroup => self.group.as_ref().and_then(|g| largest(&g.content)),
+            ImageSource::MediaThumbnail => largest(&self.thumbnail),
+            ImageSource::Enclosure => self
+                .enclosure
+                .iter()
+                .find(|e| {
+                    e.kind
+                        .as_deref()
+                        .map(|k| k.starts_with("image/"))
+                        .unwrap_or(false)
+                })
+                .map(|e| e.url.clone()),
+            ImageSource::ItunesImage => self.image.as_ref().and_then(|i| i.href.clone()),
+            ImageSource::Description => self.description.as_deref().and_then(|d| self.first_img(d)),
+            ImageSource::ContentEncoded => self.encoded.as_deref().and_then(|e| self.first_img(e)),
+        }
+        .filter(|url| !url.trim().is_empty())
+    }
+
+    /// first_img returns the src of the first <img> of `html`, resolved against the item's link
+    fn first_img(&self, html: &str) -> Option<String> {
+        let src = tag_attributes(html, "img")
+            .iter()
+            .find_map(|attributes| attribute(attributes, "src").map(|s| s.to_string()))?;
+        if Url::parse(&src).is_ok() {
+            return Some(src);
+        }
+        self.link
+            .as_deref()
+            .and_then(|link| Url::parse(link).ok())
+            .and_then(|link| link.join(&src).ok())
+            .map(|url| url.to_string())
+            .or(Some(src))
+    }
+    /// get_update_date returns, in milliseconds, the date the item was last updated at, if any
+    pub fn get_update_date(&self) -> Option<i64> {
+        self.updated
+            .as_deref()
+            .and_then(|updated| parse_date(updated.trim()).ok())
+            .map(|date| date.timestamp_millis())
+            .filter(|date| *date > 0)
+    }
+    /// get_authors returns the distinct <dc:creator> and <author> of the item
+    pub fn get_authors(&self) -> Option<Vec<String>> {
+        let mut authors: Vec<String> = vec![];
+        self.creator
+            .iter()
+            .chain(self.author.iter())
+            .map(|a| a.trim())
+            .filter(|a| !a.is_empty())
+            .for_each(|a| {
+                if !authors.iter().any(|known| known == a) {
+                    authors.push(a.to_string());
+                }
+            });
+        Some(authors).filter(|a| !a.is_empty())
+    }
+    /// get_guid returns the guid of the item and whether it is a permalink
+    pub fn get_guid(&self) -> Option<(String, bool)> {
+        let guid = self.guid.as_ref()?;
+        guid.value
+            .as_deref()
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+            .map(|v| (v.to_string(), guid.is_perma_link()))
+    }
+    pub fn get_enclosures(&self) -> Option<Vec<ArticleEnclosure>> {
+        Some(
+            self.enclosure
+                .iter()
+                .filter(|e| !e.url.trim().is_empty())
+                .map(|e| e.to_article_enclosure())
+                .collect::<Vec<ArticleEnclosure>>(),
+        )
+        .filter(|e| !e.is_empty())
+    }
+    /// get_podcast returns the podcast fields of the item, if it has an itunes tag or an audio enclosure
+    pub fn get_podcast(&self) -> Option<Podcast> {
+        let number = |n: &Option<String>| n.as_deref().and_then(|n| n.trim().parse::<i32>().ok());
+        let podcast = Podcast {
+            duration: self.duration.as_deref().and_then(parse_duration),
+            episode: number(&self.episode),
+            season: number(&self.season),
+            explicit: self.explicit.as_deref().and_then(parse_explicit),
+            image: self.get_img_from(ImageSource::ItunesImage),
+            audio: self
+                .enclosure
+                .iter()
+                .find(|e| {
+                    e.kind
+                        .as_deref()
+                        .map(|k| k.starts_with("audio/"))
+                        .unwrap_or(false)
+                })
+                .map(|e| e.to_article_enclosure()),
+        };
+        Some(podcast).filter(|p| *p != Podcast::default())
+    }
+    pub fn get_content(&self) -> Option<String> {
+        self.encoded.clone().filter(|c| !c.trim().is_empty())
+    }
     pub fn get_desc(&self) -> String {
         self.description.clone().unwrap_or_default()
     }
@@ -72,9 +330,56 @@ pub struct Channel {
     pub update_period: Option<String>,
     #[serde(alias = "updateFrequency")]
     pub update_frequency: Option<i32>,
+    // minutes
+    pub ttl: Option<String>,
+    #[serde(alias = "skipHours")]
+    pub skip_hours: Option<SkipHours>,
+    #[serde(alias = "skipDays")]
+    pub skip_days: Option<SkipDays>,
+}
+
+/// SkipHours is a <skipHours>, hours (0 to 23, GMT) during which a feed should not be fetched
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct SkipHours {
+    #[serde(default)]
+    pub hour: Vec<String>,
+}
+
+/// SkipDays is a <skipDays>, days (e.g. "Monday") during which a feed should not be fetched
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct SkipDays {
+    #[serde(default)]
+    pub day: Vec<String>,
 }
 
 impl Channel {
+    /// get_refresh_hints reads the ttl, syndication and skip hints of a feed
+    pub fn get_refresh_hints(&self) -> RefreshHints {
+        RefreshHints {
+            ttl: self
+                .ttl
+                .as_deref()
+                .and_then(|ttl| ttl.trim().parse::<i64>().ok())
+                .map(|minutes| minutes.saturating_mul(60_000)),
+            syndication: syndication_interval(self.update_period.as_deref(), self.update_frequency),
+            skip_hours: self
+                .skip_hours
+                .iter()
+                .flat_map(|skip| &skip.hour)
+                .filter_map(|hour| hour.trim().parse::<u32>().ok())
+                .filter(|hour| *hour < 24)
+                .collect(),
+            skip_days: self
+                .skip_days
+                .iter()
+                .flat_map(|skip| &skip.day)
+                .map(|day| day.trim().to_string())
+                .filter(|day| !day.is_empty())
+                .collect(),
+            ..Default::default()
+        }
+    }
+
     pub fn get_channel_name(&self, url: &str) -> String {
         self.title.clone().unwrap_or_else(move || url.to_string())
     }
@@ -0,0 +1,55 @@
+use serde::{Deserialize, Serialize};
+
+use crate::db::model::{FieldSort, PrimaryID};
+
+use super::channel::default_tenant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionStatus {
+    // sent to the hub, waiting for its verification of intent
+    Requested,
+    // verified, pushes are expected until `expires_at`
+    Active,
+    Denied,
+}
+
+/// Subscription is the WebSub subscription of a channel to the hub its feed advertises.
+/// Subscriptions live in their own collection, since they hold the secret pushes are signed with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Subscription {
+    pub channel_id: i32,
+    #[serde(default = "default_tenant")]
+    pub tenant: String,
+    pub hub: String,
+    pub topic: String,
+    pub secret: String,
+    // unguessable part of the callback url, so that only the hub calls it back
+    #[serde(default)]
+    pub token: String,
+    pub status: SubscriptionStatus,
+    pub requested_at: i64,
+    pub expires_at: Option<i64>,
+    pub last_push: Option<i64>,
+    // why the hub denied the subscription
+    pub reason: Option<String>,
+}
+
+impl Subscription {
+    /// is_active tells whether pushes are expected at `now`, leases being renewed still running
+    pub fn is_active(&self, now: i64) -> bool {
+        self.status != SubscriptionStatus::Denied && self.expires_at.map(|exp| exp > now).unwrap_or(false)
+    }
+}
+
+impl PrimaryID<i32> for Subscription {
+    fn get_primary_id(&self) -> Option<i32> {
+        Some(self.channel_id)
+    }
+}
+
+impl FieldSort<String> for Subscription {
+    fn sort_by_value(&self) -> String {
+        self.topic.clone()
+    }
+}
@@ -0,0 +1,33 @@
+use serde::{Deserialize, Serialize};
+
+use crate::db::model::{FieldSort, PrimaryID};
+
+use super::channel::default_tenant;
+
+/// Tag is a canonical tag of a tenant's taxonomy.
+/// Item categories are mapped onto it when they match its name, one of its `aliases`
+/// (compared once normalized, see `services::taxonomy::normalize_category`) or one of its `rules`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Tag {
+    #[serde(default = "default_tenant")]
+    pub tenant: String,
+    pub name: String,
+    pub label: Option<String>,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    // regexes matched against raw categories
+    #[serde(default)]
+    pub rules: Vec<String>,
+}
+
+impl PrimaryID<String> for Tag {
+    fn get_primary_id(&self) -> Option<String> {
+        Some(self.name.clone())
+    }
+}
+
+impl FieldSort<String> for Tag {
+    fn sort_by_value(&self) -> String {
+        self.name.clone()
+    }
+}
@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+/// RubyMode tells what becomes of the <ruby> annotations (furigana) of a channel's items
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RubyMode {
+    #[default]
+    Keep,
+    // only the annotated text is kept
+    Strip,
+    // annotations are removed from the text and stored in the item's `ruby`
+    Separate,
+}
+
+/// TextNormalization is the per-channel setup of `services::japanese::normalize_article`
+#[derive(Debug, Clone, Default, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct TextNormalization {
+    #[serde(default)]
+    pub ruby: RubyMode,
+    // full-width ascii and half-width katakana are turned into their usual width
+    #[serde(default)]
+    pub width: bool,
+}
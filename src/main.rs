@@ -1,16 +1,25 @@
 #![allow(async_fn_in_trait)]
 use std::{sync::Arc, time::Duration};
 
-use api::api::{healthcheck, lezgong};
+use api::{
+    api::{bakery_queue, discover_feeds, healthcheck, lezgong},
+    channels::{channels, create_channel, preview_filters},
+    items::items,
+    search::search,
+    secrets::{delete_secret, put_secret, secrets},
+    tags::{delete_tag, put_tag, tags},
+    websub::{receive_push, verify_intent},
+};
 use chrono::Utc;
 use config::Settings;
+use entities::source_type::SourceType;
 use futures::future::join_all;
 use rocket::{launch, routes};
-use services::channel::fetch_ready_channels;
+use services::channel::{fetch_ready_channels, share_between_tenants, without_paused_bakery};
 use task::spawn_tasks;
-use tokio::spawn;
+use tokio::{spawn, task::JoinHandle};
 use tokio::time::sleep;
-use utils::{DBBag, Second};
+use utils::{Clients, DBBag, Second};
 
 pub mod api;
 pub mod config;
@@ -22,30 +31,56 @@ pub mod services;
 pub mod task;
 pub mod utils;
 
-fn find_index(ledger: &Vec<i32>, value: &i32) -> Option<usize> {
-    let mut res: usize = 0;
-    for v in ledger.to_vec().iter() {
+fn find_index(ledger: &[i32], value: &i32) -> Option<usize> {
+    for (res, v) in ledger.iter().enumerate() {
         if v == value {
             return Some(res);
         }
-        res += 1;
     }
 
     None
 }
 
+async fn run_migrations(db_bag: &DBBag, settings: &Settings) {
+    match db_bag.migrations_coll.run_pending(&settings.collections).await {
+        Ok(applied) if applied.is_empty() => eprintln!("No pending migration"),
+        Ok(applied) => eprintln!("Applied migrations: {:?}", applied),
+        Err(err) => {
+            eprintln!("[ERR ] {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
 #[launch]
 async fn launch() -> _ {
     let settings = Arc::new(Settings::new().unwrap());
     let db_handle = Arc::new(db::mongo::get_handle(&settings).await);
-    let db_bag = Arc::new(DBBag::new(db_handle.clone()).unwrap());
+    let db_bag = Arc::new(DBBag::new(db_handle.clone(), &settings).unwrap());
+    // `patishie migrate` applies pending migrations, then exits
+    if std::env::args().nth(1).as_deref() == Some("migrate") {
+        run_migrations(&db_bag, &settings).await;
+        std::process::exit(0);
+    }
+    if settings.migrate_on_startup {
+        run_migrations(&db_bag, &settings).await;
+    }
+    let clients = Arc::new(Clients::new(&settings).unwrap());
+    let api_clients = clients.clone();
     let sleep_duration = Second(20).msec();
-    let mut ledger = Vec::<i32>::new();
+    // bakery refreshes wait in the bakery queue, apart from the refresh loop
+    let mut bakery_tasks = Vec::<(i32, JoinHandle<_>)>::new();
+    let api_db_bag = db_bag.clone();
+    let api_settings = settings.clone();
 
     // spawn(async move { lezgong(routes![healthcheck], 8085).await });
-    let _ = spawn(async move {
+    drop(spawn(async move {
         loop {
-            let channels = fetch_ready_channels(&db_bag.channels_coll).await;
+            let ready = without_paused_bakery(
+                fetch_ready_channels(&db_bag.channels_coll).await,
+                clients.bakery.is_available(),
+            );
+            let channels = share_between_tenants(ready, settings.max_channels_per_tenant);
             if channels.is_empty() {
                 eprintln!(
                     "({}) Didnt find any channel to refresh. Sleeping for {}",
@@ -56,8 +91,20 @@ async fn launch() -> _ {
                 continue;
             }
 
-            let mut _tasks = spawn_tasks(&channels, &settings, &db_bag, &mut ledger);
-            join_all(_tasks).await;
+            bakery_tasks.retain(|(_, task)| !task.is_finished());
+            // bakery channels still in flight are skipped
+            let mut ledger: Vec<i32> = bakery_tasks.iter().map(|(id, _)| *id).collect();
+            let bakery_ids: Vec<i32> = channels
+                .iter()
+                .filter(|c| c.source_type == SourceType::Bakery)
+                .map(|c| c.id)
+                .collect();
+            let (queued, refreshes): (Vec<_>, Vec<_>) =
+                spawn_tasks(&channels, &settings, &db_bag, &clients, &mut ledger)
+                    .into_iter()
+                    .partition(|(id, _)| bakery_ids.contains(id));
+            bakery_tasks.extend(queued);
+            join_all(refreshes.into_iter().map(|(_, task)| task)).await;
             eprintln!(
                 "({}) Done :) Sleeping for {}ms",
                 Utc::now().timestamp_millis(),
@@ -65,7 +112,31 @@ async fn launch() -> _ {
             );
             sleep(Duration::from_millis(sleep_duration + 1000)).await;
         }
-    });
+    }));
 
-    lezgong(routes![healthcheck], 8085).await
+    lezgong(
+        routes![
+            healthcheck,
+            bakery_queue,
+            discover_feeds,
+            channels,
+            create_channel,
+            preview_filters,
+            items,
+            search,
+            tags,
+            put_tag,
+            delete_tag,
+            secrets,
+            put_secret,
+            delete_secret,
+            verify_intent,
+            receive_push
+        ],
+        8085,
+        api_db_bag,
+        api_settings,
+        api_clients,
+    )
+    .await
 }
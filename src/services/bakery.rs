@@ -1,36 +1,274 @@
+use std::{sync::Mutex, time::Duration};
+
 use chrono::Utc;
-use reqwest::Client;
+use reqwest::{Client, StatusCode};
+use tokio::time::sleep;
+use url::Url;
 use uuid::Uuid;
 
-use crate::converters::string::to_articles;
-use crate::entities::potential_articles::PotentialArticle;
+use crate::{
+    config::{BakerySettings, Settings},
+    converters::string::to_bakery_response,
+    entities::{bakery_response::BakeryResponse, credential::CredentialAuth},
+    error::Error,
+    services::{
+        bakery_queue::{BakeryQueue, QueueStats},
+        credentials::{authorization, authorized_url},
+        http::client_builder,
+    },
+};
 
 const X_REQUEST_ID_LABEL: &str = "X-Request-ID";
 const NO_X_REQUEST_ID_LABEL: &str = "no_x_request_id";
+// Authorization header bakery sends when scraping the target
+const TARGET_AUTHORIZATION_LABEL: &str = "X-Target-Authorization";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BakeryError {
+    // the breaker is open, bakery is not called
+    CircuitOpen,
+    // bakery is unreachable, timed out or answered 5xx/429, even after retries
+    Unavailable(String),
+    // bakery answered with an error which retrying won't fix
+    Failed(String),
+}
+
+impl From<BakeryError> for Error {
+    fn from(value: BakeryError) -> Self {
+        match value {
+            BakeryError::CircuitOpen => Error::string("bakery circuit is open"),
+            BakeryError::Unavailable(err) => Error(format!("bakery is unavailable: {}", err)),
+            BakeryError::Failed(err) => Error(format!("bakery failed: {}", err)),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    // ms timestamp until which calls are refused
+    open_until: Option<i64>,
+}
+
+/// CircuitBreaker opens after `threshold` consecutive failures, refusing calls for `cooldown_ms`.
+/// Once the cooldown elapsed, the next call is let through: a success closes the breaker,
+/// a failure opens it again.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown_ms: i64,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown_ms: i64) -> Self {
+        CircuitBreaker {
+            threshold: threshold.max(1),
+            cooldown_ms,
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
 
-/// get_cookies_from_bakery calls bakery, a website scrapper, and returns the result
-pub async fn get_cookies_from_bakery(
-    api_path: &str,
-    channel_url: &str,
-    uuid: Uuid,
-) -> Option<Vec<PotentialArticle>> {
-    let client = Client::new();
-    let mut uuid_str = uuid.to_string();
-    if uuid_str == "" {
-        uuid_str = NO_X_REQUEST_ID_LABEL.to_string();
-    }
-    // let response = reqwest::get(format!("{}/bakery?url={}", api_path, url)).await;
-    let response = client
-        .get(format!("{}/bakery?url={}", api_path, channel_url))
-        .header(X_REQUEST_ID_LABEL, uuid_str)
-        .send()
-        .await;
-    let raw_data = match response {
-        Ok(res) => res.text().await.unwrap_or("[]".to_string()),
-        Err(err) => {
-            eprintln!("[{}] ({}) {}", uuid, Utc::now(), err);
-            return None;
-        }
-    };
-    Some(to_articles(&raw_data))
+    pub fn is_open(&self, now: i64) -> bool {
+        self.state
+            .lock()
+            .map(|state| state.open_until.map(|until| now < until).unwrap_or(false))
+            .unwrap_or(false)
+    }
+
+    pub fn record_success(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            *state = BreakerState::default();
+        }
+    }
+
+    pub fn record_failure(&self, now: i64) {
+        if let Ok(mut state) = self.state.lock() {
+            state.consecutive_failures += 1;
+            if state.consecutive_failures >= self.threshold {
+                state.open_until = Some(now + self.cooldown_ms);
+            }
+        }
+    }
+}
+
+/// bakery_url builds the url of bakery's endpoint, encoding `channel_url` in its query
+pub fn bakery_url(api_path: &str, channel_url: &str) -> Result<Url, Error> {
+    Url::parse_with_params(&format!("{}/bakery", api_path.trim_end_matches('/')), [("url", channel_url)])
+        .map_err(|err| Error(format!("invalid bakery url {}: {}", api_path, err)))
+}
+
+/// backoff_delay returns the delay before the retry following `attempt` (starting at 0):
+/// an exponential backoff, plus a random jitter of up to half of it.
+pub fn backoff_delay(base_ms: u64, attempt: u32, jitter_seed: u128) -> u64 {
+    let delay = base_ms.saturating_mul(2u64.saturating_pow(attempt));
+    delay.saturating_add((jitter_seed % (delay / 2 + 1) as u128) as u64)
+}
+
+fn is_transient(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+/// BakeryClient calls bakery, a website scrapper, sharing its connections between channels.
+#[derive(Debug)]
+pub struct BakeryClient {
+    client: Client,
+    api_path: String,
+    settings: BakerySettings,
+    breaker: CircuitBreaker,
+    queue: BakeryQueue,
+}
+
+impl BakeryClient {
+    pub fn new(settings: &Settings) -> Result<Self, Error> {
+        let bakery = &settings.bakery;
+        // the bakery is an internal service: never reached through the proxy
+        let client = client_builder(&settings.http)?
+            .timeout(Duration::from_millis(bakery.timeout_ms))
+            .no_proxy()
+            .build()
+            .map_err(|err| Error(err.to_string()))?;
+        Ok(BakeryClient {
+            client,
+            api_path: settings.api_path.clone(),
+            settings: bakery.clone(),
+            breaker: CircuitBreaker::new(bakery.breaker_threshold, bakery.breaker_cooldown_ms),
+            queue: BakeryQueue::new(
+                settings.bakery_trigger_cooldown * 1000,
+                bakery.host_cooldown * 1000,
+            ),
+        })
+    }
+
+    pub fn queue_stats(&self) -> Option<QueueStats> {
+        self.queue.stats()
+    }
+
+    pub fn is_available(&self) -> bool {
+        !self.breaker.is_open(Utc::now().timestamp_millis())
+    }
+
+    async fn try_fetch(
+        &self,
+        url: &Url,
+        authorization: Option<&str>,
+        uuid: Uuid,
+    ) -> Result<BakeryResponse, BakeryError> {
+        let mut uuid_str = uuid.to_string();
+        if uuid_str.is_empty() {
+            uuid_str = NO_X_REQUEST_ID_LABEL.to_string();
+        }
+        let mut request = self.client.get(url.clone()).header(X_REQUEST_ID_LABEL, uuid_str);
+        if let Some(authorization) = authorization {
+            request = request.header(TARGET_AUTHORIZATION_LABEL, authorization);
+        }
+        // the url may hold a query credential, it is kept out of errors
+        let response = request
+            .send()
+            .await
+            .map_err(|err| BakeryError::Unavailable(err.without_url().to_string()))?;
+        let status = response.status();
+        if is_transient(status) {
+            return Err(BakeryError::Unavailable(status.to_string()));
+        }
+        if !status.is_success() {
+            return Err(BakeryError::Failed(status.to_string()));
+        }
+        let raw_data = response
+            .text()
+            .await
+            .map_err(|err| BakeryError::Unavailable(err.without_url().to_string()))?;
+        to_bakery_response(&raw_data).map_err(|err| BakeryError::Failed(err.0))
+    }
+
+    /// get_cookies calls bakery for `channel_url` once its turn in the queue came, retrying with
+    /// a jittered backoff when bakery is unavailable. Calls are refused while the circuit breaker is open.
+    pub async fn get_cookies(
+        &self,
+        channel_id: i32,
+        channel_url: &str,
+        auth: Option<&CredentialAuth>,
+        uuid: Uuid,
+    ) -> Result<BakeryResponse, BakeryError> {
+        if !self.is_available() {
+            return Err(BakeryError::CircuitOpen);
+        }
+        let waited = self.queue.wait_turn(channel_id, channel_url).await;
+        eprintln!("[{}] ({}) bakery trigger queued for {}ms", uuid, Utc::now(), waited);
+        // the breaker may have opened while waiting
+        if !self.is_available() {
+            return Err(BakeryError::CircuitOpen);
+        }
+        let url = authorized_url(channel_url, auth)
+            .and_then(|target| bakery_url(&self.api_path, &target))
+            .map_err(|err| BakeryError::Failed(err.0))?;
+        let authorization = authorization(auth);
+        let mut attempt = 0;
+        loop {
+            match self.try_fetch(&url, authorization.as_deref(), uuid).await {
+                Ok(response) => {
+                    self.breaker.record_success();
+                    return Ok(response);
+                }
+                Err(BakeryError::Unavailable(err)) if attempt < self.settings.retries => {
+                    let delay = backoff_delay(self.settings.backoff_ms, attempt, Uuid::new_v4().as_u128());
+                    eprintln!(
+                        "[{}] ({}) bakery unavailable ({}), retrying in {}ms",
+                        uuid,
+                        Utc::now(),
+                        err,
+                        delay
+                    );
+                    sleep(Duration::from_millis(delay)).await;
+                    attempt += 1;
+                }
+                Err(err) => {
+                    if let BakeryError::Unavailable(_) = err {
+                        self.breaker.record_failure(Utc::now().timestamp_millis());
+                    }
+                    eprintln!("[{}] ({}) {}", uuid, Utc::now(), Error::from(err.clone()));
+                    return Err(err);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bakery_url() {
+        assert_eq!(
+            bakery_url("http://0.0.0.0:8084/", "https://www3.nhk.or.jp/news/easy/?a=1&b=2#top")
+                .unwrap()
+                .as_str(),
+            "http://0.0.0.0:8084/bakery?url=https%3A%2F%2Fwww3.nhk.or.jp%2Fnews%2Feasy%2F%3Fa%3D1%26b%3D2%23top"
+        );
+        assert!(bakery_url("not a url", "https://a.com").is_err());
+    }
+
+    #[test]
+    fn test_backoff_delay() {
+        assert_eq!(backoff_delay(100, 0, 0), 100);
+        assert_eq!(backoff_delay(100, 2, 0), 400);
+        assert!((400..=600).contains(&backoff_delay(100, 2, u128::MAX)));
+        assert_eq!(backoff_delay(100, 2, 7), 407);
+    }
+
+    #[test]
+    fn test_circuit_breaker() {
+        let breaker = CircuitBreaker::new(2, 1000);
+        breaker.record_failure(0);
+        assert!(!breaker.is_open(0));
+        breaker.record_failure(10);
+        assert!(breaker.is_open(500));
+        assert!(!breaker.is_open(1010));
+        // half open: a new failure opens it again
+        breaker.record_failure(1010);
+        assert!(breaker.is_open(1500));
+        breaker.record_success();
+        assert!(!breaker.is_open(1500));
+    }
 }
@@ -0,0 +1,243 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::Duration,
+};
+
+use chrono::Utc;
+use serde::Serialize;
+use tokio::time::sleep;
+use url::Url;
+
+// upper bound of a waiter's sleep, so that it notices entries leaving the queue
+const MAX_POLL_MS: i64 = 500;
+// amount of wait times kept for the queue's statistics
+const RECENT_WAITS: usize = 100;
+
+#[derive(Debug, Clone)]
+struct Entry {
+    id: u64,
+    channel_id: i32,
+    host: String,
+    enqueued_at: i64,
+}
+
+/// Scheduler decides which queued scrape triggers may go: at most one every `cooldown_ms`,
+/// and one every `host_cooldown_ms` per target host. Entries go in order, unless their host is cooling down.
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    cooldown_ms: i64,
+    host_cooldown_ms: i64,
+    next_id: u64,
+    last_trigger: Option<i64>,
+    last_host_triggers: HashMap<String, i64>,
+    queue: VecDeque<Entry>,
+    recent_waits: VecDeque<i64>,
+}
+
+impl Scheduler {
+    pub fn new(cooldown_ms: i64, host_cooldown_ms: i64) -> Self {
+        Scheduler {
+            cooldown_ms,
+            host_cooldown_ms,
+            ..Default::default()
+        }
+    }
+
+    pub fn enqueue(&mut self, channel_id: i32, host: &str, now: i64) -> u64 {
+        self.next_id += 1;
+        self.queue.push_back(Entry {
+            id: self.next_id,
+            channel_id,
+            host: host.to_string(),
+            enqueued_at: now,
+        });
+        self.next_id
+    }
+
+    /// ready_at returns the date a trigger to `host` is allowed at
+    fn ready_at(&self, host: &str) -> i64 {
+        let global = self.last_trigger.map(|t| t + self.cooldown_ms).unwrap_or(0);
+        let per_host = self
+            .last_host_triggers
+            .get(host)
+            .map(|t| t + self.host_cooldown_ms)
+            .unwrap_or(0);
+        global.max(per_host)
+    }
+
+    /// try_take lets the entry `id` go when it is the first entry allowed to, recording its trigger.
+    /// Otherwise, returns how long to wait before trying again.
+    pub fn try_take(&mut self, id: u64, now: i64) -> Result<i64, i64> {
+        let first_ready = self
+            .queue
+            .iter()
+            .position(|entry| self.ready_at(&entry.host) <= now);
+        match first_ready.filter(|i| self.queue[*i].id == id).and_then(|i| self.queue.remove(i)) {
+            Some(entry) => {
+                self.last_trigger = Some(now);
+                self.last_host_triggers.insert(entry.host, now);
+                let waited = now - entry.enqueued_at;
+                self.recent_waits.push_back(waited);
+                if self.recent_waits.len() > RECENT_WAITS {
+                    self.recent_waits.pop_front();
+                }
+                Ok(waited)
+            }
+            _ => {
+                let wait = self
+                    .queue
+                    .iter()
+                    .map(|entry| self.ready_at(&entry.host) - now)
+                    .min()
+                    .unwrap_or(0);
+                Err(wait.clamp(1, MAX_POLL_MS))
+            }
+        }
+    }
+
+    /// cancel removes an entry which won't wait for its turn anymore
+    pub fn cancel(&mut self, id: u64) {
+        self.queue.retain(|entry| entry.id != id);
+    }
+
+    pub fn stats(&self, now: i64) -> QueueStats {
+        QueueStats {
+            depth: self.queue.len(),
+            cooldown_ms: self.cooldown_ms,
+            host_cooldown_ms: self.host_cooldown_ms,
+            waiting: self
+                .queue
+                .iter()
+                .map(|entry| QueuedTrigger {
+                    channel_id: entry.channel_id,
+                    host: entry.host.clone(),
+                    waited_ms: now - entry.enqueued_at,
+                    ready_in_ms: (self.ready_at(&entry.host) - now).max(0),
+                })
+                .collect(),
+            avg_wait_ms: match self.recent_waits.is_empty() {
+                true => 0,
+                false => self.recent_waits.iter().sum::<i64>() / self.recent_waits.len() as i64,
+            },
+            max_wait_ms: self.recent_waits.iter().copied().max().unwrap_or(0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct QueuedTrigger {
+    pub channel_id: i32,
+    pub host: String,
+    pub waited_ms: i64,
+    // 0 when only waiting for entries ahead in the queue
+    pub ready_in_ms: i64,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct QueueStats {
+    pub depth: usize,
+    pub cooldown_ms: i64,
+    pub host_cooldown_ms: i64,
+    pub waiting: Vec<QueuedTrigger>,
+    // over the last triggers
+    pub avg_wait_ms: i64,
+    pub max_wait_ms: i64,
+}
+
+/// Ticket leaves the queue when dropped, so that a waiter which gave up never blocks the queue
+struct Ticket<'a> {
+    queue: &'a BakeryQueue,
+    id: u64,
+}
+
+impl Drop for Ticket<'_> {
+    fn drop(&mut self) {
+        self.queue.cancel(self.id);
+    }
+}
+
+/// BakeryQueue makes bakery channels wait for their turn before triggering a scrape
+#[derive(Debug)]
+pub struct BakeryQueue(Mutex<Scheduler>);
+
+impl BakeryQueue {
+    pub fn new(cooldown_ms: i64, host_cooldown_ms: i64) -> Self {
+        BakeryQueue(Mutex::new(Scheduler::new(cooldown_ms, host_cooldown_ms)))
+    }
+
+    /// wait_turn queues a scrape of `url` and returns, once allowed to trigger it, how long it waited
+    pub async fn wait_turn(&self, channel_id: i32, url: &str) -> i64 {
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| h.to_string()))
+            .unwrap_or_default();
+        let ticket = match self.0.lock() {
+            Ok(mut scheduler) => Ticket {
+                queue: self,
+                id: scheduler.enqueue(channel_id, &host, Utc::now().timestamp_millis()),
+            },
+            Err(_) => return 0,
+        };
+        loop {
+            let turn = match self.0.lock() {
+                Ok(mut scheduler) => scheduler.try_take(ticket.id, Utc::now().timestamp_millis()),
+                Err(_) => return 0,
+            };
+            match turn {
+                Ok(waited) => return waited,
+                Err(wait) => sleep(Duration::from_millis(wait as u64)).await,
+            }
+        }
+    }
+
+    fn cancel(&self, id: u64) {
+        if let Ok(mut scheduler) = self.0.lock() {
+            scheduler.cancel(id);
+        }
+    }
+
+    pub fn stats(&self) -> Option<QueueStats> {
+        self.0
+            .lock()
+            .ok()
+            .map(|scheduler| scheduler.stats(Utc::now().timestamp_millis()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scheduler_cooldowns() {
+        let mut scheduler = Scheduler::new(100, 1000);
+        let a = scheduler.enqueue(1, "a.com", 0);
+        let b = scheduler.enqueue(2, "a.com", 0);
+        let c = scheduler.enqueue(3, "b.com", 0);
+        assert_eq!(scheduler.try_take(b, 0), Err(1));
+        assert_eq!(scheduler.try_take(a, 0), Ok(0));
+        // global cooldown
+        assert_eq!(scheduler.try_take(c, 50), Err(50));
+        // b is still cooling down for a.com, c goes first
+        assert_eq!(scheduler.try_take(b, 100), Err(1));
+        assert_eq!(scheduler.try_take(c, 100), Ok(100));
+        assert_eq!(scheduler.stats(200).waiting[0].ready_in_ms, 800);
+        assert_eq!(scheduler.try_take(b, 500), Err(MAX_POLL_MS));
+        assert_eq!(scheduler.try_take(b, 1000), Ok(1000));
+        let stats = scheduler.stats(1000);
+        assert_eq!(stats.depth, 0);
+        assert_eq!(stats.max_wait_ms, 1000);
+        assert_eq!(stats.avg_wait_ms, 366);
+    }
+
+    #[test]
+    fn test_scheduler_cancel() {
+        let mut scheduler = Scheduler::new(100, 100);
+        let a = scheduler.enqueue(1, "a.com", 0);
+        let b = scheduler.enqueue(2, "b.com", 0);
+        scheduler.cancel(a);
+        assert_eq!(scheduler.try_take(b, 0), Ok(0));
+        assert_eq!(scheduler.stats(0).depth, 0);
+    }
+}
@@ -1,9 +1,15 @@
+use std::collections::BTreeMap;
+
 use chrono::Utc;
-use mongodb::bson::doc;
+use mongodb::bson::{doc, Document};
 
 use crate::{
-    db::{channel::Channels, model::CollectionModel},
-    entities::channel::Channel,
+    db::{
+        channel::Channels,
+        model::CollectionModel,
+        pipeline::{FieldMatcher, Pipe, Pipeline},
+    },
+    entities::{channel::Channel, source_type::SourceType},
 };
 
 impl Eq for &Channel {}
@@ -14,7 +20,7 @@ impl Ord for &Channel {
     }
 }
 
-pub fn get_shortest_sleep(refresh_time: Option<i64>, channels: &Vec<Channel>) -> Option<u64> {
+pub fn get_shortest_sleep(refresh_time: Option<i64>, channels: &[Channel]) -> Option<u64> {
     if channels.is_empty() {
         return None;
     }
@@ -28,27 +34,131 @@ pub fn get_shortest_sleep(refresh_time: Option<i64>, channels: &Vec<Channel>) ->
     })
 }
 
+/// ready_channels_pipeline matches enabled channels which `last_refresh + refresh_frequency` is past `now`.
+/// Channels missing either field are ready: in an `$expr`, null compares lower than `now`.
+pub fn ready_channels_pipeline(now: &i64) -> Vec<Document> {
+    Pipeline::from_slice(&[
+        Pipe::Match("disabled_at", FieldMatcher::<i64>::Null),
+        Pipe::MatchExpr(doc! {
+            "$lte": [{"$add": ["$last_refresh", "$refresh_frequency"]}, now]
+        }),
+    ])
+    .build()
+}
+
 pub async fn fetch_ready_channels(channels_coll: &Channels<Channel>) -> Vec<Channel> {
     channels_coll
-        .find(
-            doc! {
-                "$expr": {
-                    "$lte": [
-                        { "$add": ["$last_refresh", "$refresh_frequency"] },
-                        Utc::now().timestamp_millis()
-                    ]
-                }
-            },
-            None,
-            None,
-        )
+        .find_aggregate(ready_channels_pipeline(&Utc::now().timestamp_millis()))
         .await
         .unwrap_or_default()
-    // .find_aggregate(Pipeline::single_add_lt(
-    //     "next_refresh",
-    //     &["last_refresh", "refresh_frequency"],
-    //     &Utc::now().timestamp_millis(),
-    // ))
-    // .await
-    // .unwrap_or_default()
+}
+
+/// without_paused_bakery drops the bakery channels while bakery is not `available`: paused,
+/// they are not refreshed and would otherwise take the place of their tenant's other channels.
+pub fn without_paused_bakery(channels: Vec<Channel>, available: bool) -> Vec<Channel> {
+    channels
+        .into_iter()
+        .filter(|channel| available || channel.source_type != SourceType::Bakery)
+        .collect()
+}
+
+/// share_between_tenants keeps at most `max_per_tenant` channels for each tenant,
+/// least recently refreshed first, so that a tenant with many ready channels
+/// does not starve the others. Channels are then interleaved tenant by tenant.
+/// A `max_per_tenant` of 0 means no limit.
+pub fn share_between_tenants(channels: Vec<Channel>, max_per_tenant: usize) -> Vec<Channel> {
+    let mut by_tenant: BTreeMap<String, Vec<Channel>> = BTreeMap::new();
+    for channel in channels {
+        by_tenant
+            .entry(channel.tenant.clone())
+            .or_default()
+            .push(channel);
+    }
+    let mut queues: Vec<_> = by_tenant
+        .into_values()
+        .map(|mut tenant_channels| {
+            tenant_channels.sort_by_key(|c| c.last_refresh);
+            if max_per_tenant > 0 {
+                tenant_channels.truncate(max_per_tenant);
+            }
+            tenant_channels.into_iter()
+        })
+        .collect();
+    let mut res = vec![];
+    loop {
+        let before = res.len();
+        queues
+            .iter_mut()
+            .for_each(|queue| res.extend(queue.next()));
+        if res.len() == before {
+            return res;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mongodb::bson::doc;
+
+    fn channel(tenant: &str, id: i32, last_refresh: i64) -> Channel {
+        let mut c = Channel::new(tenant, &format!("c{}", id), "https://example.com", SourceType::RSSFeed);
+        c.id = id;
+        c.last_refresh = last_refresh;
+        c
+    }
+
+    #[test]
+    fn test_ready_channels_pipeline() {
+        assert_eq!(
+            ready_channels_pipeline(&1000),
+            vec![
+                doc! {"$match": {"disabled_at": {"$eq": null}}},
+                doc! {"$match": {"$expr": {"$lte": [{"$add": ["$last_refresh", "$refresh_frequency"]}, 1000_i64]}}},
+            ]
+        );
+    }
+
+    #[test]
+    fn test_channels_missing_last_refresh_are_ready() {
+        // a query `$lte` never matches the null sum of a channel missing `last_refresh`,
+        // the readiness check must stay an aggregation expression
+        let pipeline = ready_channels_pipeline(&1000);
+        let readiness = pipeline.last().unwrap().get_document("$match").unwrap();
+        assert!(readiness.contains_key("$expr"));
+        assert!(!pipeline.iter().any(|stage| stage.contains_key("$addFields")));
+    }
+
+    #[test]
+    fn test_share_between_tenants() {
+        let channels = vec![
+            channel("a", 1, 30),
+            channel("a", 2, 10),
+            channel("a", 3, 20),
+            channel("b", 4, 50),
+        ];
+        let ids: Vec<i32> = share_between_tenants(channels.clone(), 2)
+            .iter()
+            .map(|c| c.id)
+            .collect();
+        assert_eq!(ids, vec![2, 4, 3]);
+
+        let ids: Vec<i32> = share_between_tenants(channels, 0)
+            .iter()
+            .map(|c| c.id)
+            .collect();
+        assert_eq!(ids, vec![2, 4, 3, 1]);
+    }
+
+    #[test]
+    fn test_without_paused_bakery() {
+        let mut channels: Vec<Channel> = (1..=3).map(|id| channel("a", id, id as i64)).collect();
+        channels[0].source_type = SourceType::Bakery;
+        channels[1].source_type = SourceType::Bakery;
+        channels.push(channel("a", 4, 40));
+        let ids = |channels: Vec<Channel>| channels.iter().map(|c| c.id).collect::<Vec<i32>>();
+        assert_eq!(ids(share_between_tenants(without_paused_bakery(channels.clone(), true), 2)), vec![1, 2]);
+        // rss channels are not starved by bakery channels left ready while bakery is down
+        assert_eq!(ids(share_between_tenants(without_paused_bakery(channels, false), 2)), vec![3, 4]);
+    }
 }
@@ -0,0 +1,183 @@
+use encoding_rs::{Encoding, EUC_JP, SHIFT_JIS, UTF_8, WINDOWS_1252};
+
+use crate::entities::detected_encoding::{CharsetSource, DetectedEncoding};
+
+/// Encodings tried, in order, on bodies which are neither labelled nor valid utf-8.
+/// EUC-JP goes first: its text mostly decodes as Shift_JIS half-width katakana without errors,
+/// while Shift_JIS text rarely is valid EUC-JP. Windows-1252 decodes anything.
+const FALLBACK_ENCODINGS: &[&Encoding] = &[EUC_JP, SHIFT_JIS, WINDOWS_1252];
+
+/// header_charset reads the charset of a Content-Type header
+pub fn header_charset(content_type: &str) -> Option<&'static Encoding> {
+    content_type
+        .split(';')
+        .find_map(|param| {
+            let (name, value) = param.split_once('=')?;
+            name.trim().eq_ignore_ascii_case("charset").then_some(value)
+        })
+        .and_then(|charset| Encoding::for_label(charset.trim().trim_matches('"').as_bytes()))
+}
+
+/// declared_charset reads the encoding of the <?xml ?> declaration which starts `body`.
+/// A declared utf-16 is read as utf-8, since utf-16 bodies start with a BOM.
+pub fn declared_charset(body: &[u8]) -> Option<&'static Encoding> {
+    let head = &body[..body.len().min(1024)];
+    if !head.starts_with(b"<?xml") {
+        return None;
+    }
+    let end = head.windows(2).position(|w| w == b"?>")?;
+    let declaration = String::from_utf8_lossy(&head[..end]);
+    let (_, rest) = declaration.split_once("encoding")?;
+    let rest = rest.trim_start().strip_prefix('=')?.trim_start();
+    let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let (label, _) = rest[1..].split_once(quote)?;
+    Encoding::for_label(label.trim().as_bytes()).map(Encoding::output_encoding)
+}
+
+/// detect guesses the encoding of an unlabelled body: utf-8 when valid, else the first
+/// fallback encoding decoding it without errors
+pub fn detect(body: &[u8]) -> &'static Encoding {
+    if std::str::from_utf8(body).is_ok() {
+        return UTF_8;
+    }
+    FALLBACK_ENCODINGS
+        .iter()
+        .copied()
+        .find(|encoding| {
+            let (_, had_errors) = encoding.decode_without_bom_handling(body);
+            !had_errors
+        })
+        .unwrap_or(WINDOWS_1252)
+}
+
+/// normalize_declaration marks the <?xml ?> declaration of a transcoded body as utf-8,
+/// so xml parsers do not decode it again
+pub fn normalize_declaration(text: &str) -> String {
+    let Some(end) = text.starts_with("<?xml").then(|| text.find("?>")).flatten() else {
+        return text.to_string();
+    };
+    let (declaration, rest) = text.split_at(end);
+    let Some(start) = declaration.find("encoding") else {
+        return text.to_string();
+    };
+    let value = &declaration[start..];
+    let quoted = value
+        .find(['"', '\''])
+        .and_then(|open| {
+            let quote = value[open..].chars().next()?;
+            let close = value[open + 1..].find(quote)?;
+            Some(open + 1 + close + 1)
+        });
+    match quoted {
+        Some(len) => format!(
+            "{}encoding=\"UTF-8\"{}{}",
+            &declaration[..start],
+            &declaration[start + len..],
+            rest
+        ),
+        None => text.to_string(),
+    }
+}
+
+/// transcode decodes a fetched body to utf-8, with the encoding of its BOM, else the charset
+/// of its `content_type`, else the one of its xml declaration, else a detected one.
+/// The xml declaration of the result is marked as utf-8.
+pub fn transcode(body: &[u8], content_type: Option<&str>) -> (String, DetectedEncoding) {
+    let (encoding, source, body) = match Encoding::for_bom(body) {
+        Some((encoding, bom_length)) => (encoding, CharsetSource::Bom, &body[bom_length..]),
+        None => match content_type.and_then(header_charset) {
+            Some(encoding) => (encoding, CharsetSource::Header, body),
+            None => match declared_charset(body) {
+                Some(encoding) => (encoding, CharsetSource::Declaration, body),
+                None => (detect(body), CharsetSource::Detected, body),
+            },
+        },
+    };
+    let (text, lossy) = encoding.decode_without_bom_handling(body);
+    (
+        normalize_declaration(&text),
+        DetectedEncoding {
+            name: encoding.name().to_string(),
+            source,
+            lossy,
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FEED: &str = "<?xml version=\"1.0\" encoding=\"Shift_JIS\"?><rss><channel><title>ニュース</title></channel></rss>";
+
+    #[test]
+    fn test_header_charset() {
+        assert_eq!(header_charset("text/xml; charset=\"ISO-8859-1\""), Some(WINDOWS_1252));
+        assert_eq!(header_charset("application/rss+xml;Charset=shift_jis"), Some(SHIFT_JIS));
+        assert_eq!(header_charset("text/xml"), None);
+    }
+
+    #[test]
+    fn test_declared_charset() {
+        assert_eq!(declared_charset(FEED.as_bytes()), Some(SHIFT_JIS));
+        assert_eq!(declared_charset(b"<?xml version='1.0' encoding = 'euc-jp' ?><rss/>"), Some(EUC_JP));
+        assert_eq!(declared_charset(b"<?xml version=\"1.0\" encoding=\"UTF-16\"?>"), Some(UTF_8));
+        assert_eq!(declared_charset(b"<?xml version=\"1.0\"?><rss/>"), None);
+        assert_eq!(declared_charset(b"<rss encoding=\"EUC-JP\"/>"), None);
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect("日本語".as_bytes()), UTF_8);
+        let (sjis, _, _) = SHIFT_JIS.encode("こんにちは、世界");
+        assert_eq!(detect(&sjis), SHIFT_JIS);
+        let (euc, _, _) = EUC_JP.encode("こんにちは、世界");
+        assert_eq!(detect(&euc), EUC_JP);
+        let (latin1, _, _) = WINDOWS_1252.encode("café");
+        assert_eq!(detect(&latin1), WINDOWS_1252);
+    }
+
+    #[test]
+    fn test_normalize_declaration() {
+        assert_eq!(
+            normalize_declaration(FEED),
+            FEED.replace("encoding=\"Shift_JIS\"", "encoding=\"UTF-8\"")
+        );
+        assert_eq!(
+            normalize_declaration("<?xml version='1.0' encoding='euc-jp' standalone='yes'?><rss/>"),
+            "<?xml version='1.0' encoding=\"UTF-8\" standalone='yes'?><rss/>"
+        );
+        assert_eq!(normalize_declaration("<rss/>"), "<rss/>");
+    }
+
+    #[test]
+    fn test_transcode() {
+        let (sjis, _, _) = SHIFT_JIS.encode(FEED);
+        let (text, encoding) = transcode(&sjis, Some("application/rss+xml"));
+        assert!(text.contains("<title>ニュース</title>"));
+        assert!(text.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>"));
+        assert_eq!(
+            encoding,
+            DetectedEncoding {
+                name: "Shift_JIS".to_string(),
+                source: CharsetSource::Declaration,
+                lossy: false,
+            }
+        );
+        // the header wins over the declaration, the BOM over both
+        let (_, encoding) = transcode(&sjis, Some("text/xml; charset=Shift_JIS"));
+        assert_eq!(encoding.source, CharsetSource::Header);
+        let bom = [b"\xEF\xBB\xBF".as_slice(), "<rss>café</rss>".as_bytes()].concat();
+        let (text, encoding) = transcode(&bom, Some("text/xml; charset=ISO-8859-1"));
+        assert_eq!(text, "<rss>café</rss>");
+        assert_eq!((encoding.name.as_str(), encoding.source), ("UTF-8", CharsetSource::Bom));
+        let (euc, _, _) = EUC_JP.encode("<rss>ニュース</rss>");
+        let (text, encoding) = transcode(&euc, None);
+        assert_eq!(text, "<rss>ニュース</rss>");
+        assert_eq!((encoding.name.as_str(), encoding.source), ("EUC-JP", CharsetSource::Detected));
+        let (latin1, _, _) = WINDOWS_1252.encode("café");
+        assert_eq!(transcode(&latin1, Some("text/xml; charset=\"ISO-8859-1\"")).0, "café");
+        assert_eq!(transcode("café".as_bytes(), Some("text/xml")).0, "café");
+        assert_eq!(transcode("café".as_bytes(), None).0, "café");
+    }
+}
@@ -0,0 +1,489 @@
+use crate::entities::potential_articles::PotentialArticle;
+
+/// Tags kept by `sanitize_html`, with their allowed attributes
+const ALLOWED_TAGS: &[(&str, &[&str])] = &[
+    ("a", &["href", "title"]),
+    ("abbr", &["title"]),
+    ("b", &[]),
+    ("blockquote", &["cite"]),
+    ("br", &[]),
+    ("code", &[]),
+    ("em", &[]),
+    ("figcaption", &[]),
+    ("figure", &[]),
+    ("h1", &[]),
+    ("h2", &[]),
+    ("h3", &[]),
+    ("h4", &[]),
+    ("h5", &[]),
+    ("h6", &[]),
+    ("hr", &[]),
+    ("i", &[]),
+    ("img", &["src", "alt", "title", "width", "height"]),
+    ("li", &[]),
+    ("ol", &[]),
+    ("p", &[]),
+    ("pre", &[]),
+    ("q", &["cite"]),
+    ("rb", &[]),
+    ("rp", &[]),
+    ("rt", &[]),
+    ("ruby", &[]),
+    ("s", &[]),
+    ("small", &[]),
+    ("strong", &[]),
+    ("sub", &[]),
+    ("sup", &[]),
+    ("u", &[]),
+    ("ul", &[]),
+];
+/// Tags removed along with their content
+const DROPPED_WITH_CONTENT: &[&str] = &[
+    "script", "style", "iframe", "object", "embed", "noscript", "template", "svg", "form",
+];
+const VOID_TAGS: &[&str] = &["br", "hr", "img"];
+const URL_ATTRIBUTES: &[&str] = &["href", "src", "cite"];
+const ALLOWED_SCHEMES: &[&str] = &["http://", "https://", "mailto:"];
+/// Hosts and paths only used to serve tracking pixels
+const TRACKING_PATTERNS: &[&str] = &[
+    "feeds.feedburner.com/~r/",
+    "feedburner.com/~ff/",
+    "stats.wordpress.com",
+    "pixel.wp.com",
+    "/pixel.gif",
+    "/pixel.png",
+    "doubleclick.net",
+    "google-analytics.com",
+    "pi.feedsportal.com",
+];
+
+#[derive(Debug, PartialEq)]
+pub enum Token<'a> {
+    Text(&'a str),
+    Open {
+        name: String,
+        attributes: Vec<(String, String)>,
+        self_closing: bool,
+    },
+    Close(String),
+}
+
+/// tokenize splits `html` into texts and tags, each with the markup it was read from.
+/// Comments, doctypes and processing instructions are skipped.
+pub fn tokenize(html: &str) -> Vec<(Token<'_>, &str)> {
+    let mut tokens = vec![];
+    let mut rest = html;
+    while !rest.is_empty() {
+        let Some(lt) = rest.find('<') else {
+            tokens.push((Token::Text(rest), rest));
+            break;
+        };
+        if lt > 0 {
+            tokens.push((Token::Text(&rest[..lt]), &rest[..lt]));
+            rest = &rest[lt..];
+        }
+        let start = rest;
+        let token = if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map(|i| &comment[i + 3..]).unwrap_or("");
+            None
+        } else if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+            let end = cdata.find("]]>").unwrap_or(cdata.len());
+            rest = cdata.get(end + 3..).unwrap_or("");
+            Some(Token::Text(&cdata[..end]))
+        } else if rest.starts_with("<!") || rest.starts_with("<?") {
+            rest = rest.find('>').map(|i| &rest[i + 1..]).unwrap_or("");
+            None
+        } else if let Some(close) = rest.strip_prefix("</") {
+            let end = close.find('>').unwrap_or(close.len());
+            rest = close.get(end + 1..).unwrap_or("");
+            Some(Token::Close(close[..end].trim().to_lowercase()))
+        } else if rest[1..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+            let (token, remaining) = parse_open_tag(&rest[1..]);
+            rest = remaining;
+            Some(token)
+        } else {
+            rest = &rest[1..];
+            Some(Token::Text(&start[..1]))
+        };
+        if let Some(token) = token {
+            tokens.push((token, &start[..start.len() - rest.len()]));
+        }
+    }
+    tokens
+}
+
+/// parse_open_tag parses a tag which opening `<` was already consumed
+fn parse_open_tag(input: &str) -> (Token<'_>, &str) {
+    let name_end = input
+        .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .unwrap_or(input.len());
+    let name = input[..name_end].to_lowercase();
+    let mut rest = &input[name_end..];
+    let mut attributes = vec![];
+    let mut self_closing = false;
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            break;
+        }
+        if let Some(r) = rest.strip_prefix("/>") {
+            self_closing = true;
+            rest = r;
+            break;
+        }
+        if let Some(r) = rest.strip_prefix('>') {
+            rest = r;
+            break;
+        }
+        if let Some(r) = rest.strip_prefix('/') {
+            rest = r;
+            continue;
+        }
+        let attr_end = rest
+            .find(|c: char| c.is_whitespace() || c == '=' || c == '>' || c == '/')
+            .unwrap_or(rest.len())
+            .max(1);
+        let attr_name = rest[..attr_end].to_lowercase();
+        rest = rest[attr_end..].trim_start();
+        let mut value = String::new();
+        if let Some(r) = rest.strip_prefix('=') {
+            let r = r.trim_start();
+            if let Some(quote) = r.chars().next().filter(|c| *c == '"' || *c == '\'') {
+                let quoted = &r[1..];
+                let end = quoted.find(quote).unwrap_or(quoted.len());
+                value = quoted[..end].to_string();
+                rest = quoted.get(end + 1..).unwrap_or("");
+            } else {
+                let end = r
+                    .find(|c: char| c.is_whitespace() || c == '>')
+                    .unwrap_or(r.len());
+                value = r[..end].to_string();
+                rest = &r[end..];
+            }
+        }
+        attributes.push((attr_name, value));
+    }
+    (
+        Token::Open {
+            name,
+            attributes,
+            self_closing,
+        },
+        rest,
+    )
+}
+
+fn escape_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// is_safe_url accepts urls with an allowed scheme, and paths of the same host.
+/// Browsers drop tabs and newlines from urls and read `\` as `/`, so `/\evil.com` or `/\t/evil.com`
+/// would be protocol-relative urls to another host.
+fn is_safe_url(url: &str) -> bool {
+    let lower: String = url
+        .trim()
+        .chars()
+        .filter(|c| !matches!(c, '\t' | '\n' | '\r'))
+        .collect::<String>()
+        .to_lowercase();
+    ALLOWED_SCHEMES.iter().any(|s| lower.starts_with(s))
+        || (lower.starts_with('/') && !matches!(lower.chars().nth(1), Some('/') | Some('\\')))
+}
+
+fn is_tracking_pixel(attributes: &[(String, String)]) -> bool {
+    let attribute = |name: &str| {
+        attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.trim().to_lowercase())
+    };
+    let tiny = |name: &str| {
+        attribute(name)
+            .and_then(|v| v.trim_end_matches("px").parse::<u32>().ok())
+            .map(|v| v <= 1)
+            .unwrap_or(false)
+    };
+    let tracked_src = attribute("src")
+        .map(|src| TRACKING_PATTERNS.iter().any(|p| src.contains(p)))
+        .unwrap_or(false);
+    (tiny("width") && tiny("height")) || tracked_src
+}
+
+/// sanitize_html keeps the allow-listed tags and attributes of `html`.
+/// Scripts, styles, iframes and other embeds are removed with their content,
+/// so are tracking pixels, comments and urls which scheme is not http(s) or mailto.
+pub fn sanitize_html(html: &str) -> String {
+    let mut res = String::with_capacity(html.len());
+    // depth inside tags which content is dropped
+    let mut dropping = 0;
+    let mut open_tags: Vec<String> = vec![];
+    for (token, _) in tokenize(html) {
+        match token {
+            Token::Open {
+                name,
+                self_closing,
+                ..
+            } if DROPPED_WITH_CONTENT.contains(&name.as_str()) => {
+                if !self_closing {
+                    dropping += 1;
+                }
+            }
+            Token::Close(name) if DROPPED_WITH_CONTENT.contains(&name.as_str()) => {
+                dropping = std::cmp::max(dropping - 1, 0);
+            }
+            _ if dropping > 0 => {}
+            Token::Text(text) => res.push_str(&text.replace('<', "&lt;").replace('>', "&gt;")),
+            Token::Open {
+                name, attributes, ..
+            } => {
+                let Some((_, allowed)) = ALLOWED_TAGS.iter().find(|(tag, _)| *tag == name) else {
+                    continue;
+                };
+                if name == "img" && is_tracking_pixel(&attributes) {
+                    continue;
+                }
+                res.push('<');
+                res.push_str(&name);
+                for (attr_name, value) in attributes {
+                    // checked and written back as browsers read it, e.g. `&#9;` as a tab
+                    let value = decode_entities(&value);
+                    if !allowed.contains(&attr_name.as_str())
+                        || (URL_ATTRIBUTES.contains(&attr_name.as_str()) && !is_safe_url(&value))
+                    {
+                        continue;
+                    }
+                    res.push_str(&format!(" {}=\"{}\"", attr_name, escape_attribute(&value)));
+                }
+                if name == "a" {
+                    res.push_str(" rel=\"nofollow noopener\"");
+                }
+                res.push('>');
+                if !VOID_TAGS.contains(&name.as_str()) {
+                    open_tags.push(name);
+                }
+            }
+            Token::Close(name) => {
+                if let Some(pos) = open_tags.iter().rposition(|t| *t == name) {
+                    // closes tags left open in between
+                    for tag in open_tags.drain(pos..).rev() {
+                        res.push_str(&format!("</{}>", tag));
+                    }
+                }
+            }
+        }
+    }
+    for tag in open_tags.iter().rev() {
+        res.push_str(&format!("</{}>", tag));
+    }
+    res.trim().to_string()
+}
+
+/// tag_attributes returns the attributes of every `tag` found in `html`, in order of appearance.
+/// Attribute names are lowercased.
+pub fn tag_attributes(html: &str, tag: &str) -> Vec<Vec<(String, String)>> {
+    tokenize(html)
+        .into_iter()
+        .filter_map(|(token, _)| match token {
+            Token::Open {
+                name, attributes, ..
+            } if name == tag => Some(attributes),
+            _ => None,
+        })
+        .collect()
+}
+
+/// attribute returns the value of the attribute `name`, among `attributes`
+pub fn attribute<'a>(attributes: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    attributes
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, v)| v.as_str())
+}
+
+/// decode_entities decodes named xml/html entities commonly found in feeds, and numeric ones
+pub fn decode_entities(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        res.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let decoded = rest.find(';').filter(|end| *end <= 10).and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                "hellip" => Some('…'),
+                "mdash" => Some('—'),
+                "ndash" => Some('–'),
+                "lsquo" => Some('‘'),
+                "rsquo" => Some('’'),
+                "ldquo" => Some('“'),
+                "rdquo" => Some('”'),
+                "copy" => Some('©'),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .map(|hex| u32::from_str_radix(hex, 16).ok())
+                    .unwrap_or_else(|| entity.strip_prefix('#').and_then(|d| d.parse().ok()))
+                    .and_then(char::from_u32),
+            };
+            c.map(|c| (c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                res.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                res.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    res.push_str(rest);
+    res
+}
+
+/// to_plain_text removes every tag of `html` (and the content of scripts and styles),
+/// decodes entities and collapses whitespaces.
+pub fn to_plain_text(html: &str) -> String {
+    let mut res = String::with_capacity(html.len());
+    let mut dropping = 0;
+    for (token, _) in tokenize(html) {
+        match token {
+            Token::Open {
+                name,
+                self_closing,
+                ..
+            } if DROPPED_WITH_CONTENT.contains(&name.as_str()) => {
+                if !self_closing {
+                    dropping += 1;
+                }
+            }
+            Token::Close(name) if DROPPED_WITH_CONTENT.contains(&name.as_str()) => {
+                dropping = std::cmp::max(dropping - 1, 0);
+            }
+            _ if dropping > 0 => {}
+            Token::Text(text) => res.push_str(&decode_entities(text)),
+            // tags separate words
+            _ => res.push(' '),
+        }
+    }
+    res.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// excerpt truncates `text` to at most `max_chars` chars, cutting on a word boundary when possible.
+/// A `max_chars` of 0 means no limit.
+pub fn excerpt(text: &str, max_chars: usize) -> String {
+    if max_chars == 0 || text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let cut: String = text.chars().take(max_chars).collect();
+    let truncated = match cut.rfind(char::is_whitespace) {
+        // don't cut too much of a text without spaces (e.g. japanese)
+        Some(space) if space > cut.len() / 2 => &cut[..space],
+        _ => &cut,
+    };
+    format!("{}…", truncated.trim_end())
+}
+
+/// process_content fills the sanitized html and plain text excerpt of an article from its raw description
+pub fn process_content(article: &mut PotentialArticle, excerpt_length: usize) {
+    article.desc_html = Some(sanitize_html(&article.desc));
+    article.excerpt = Some(excerpt(&to_plain_text(&article.desc), excerpt_length));
+    article.content = article.content.as_deref().map(sanitize_html);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize() {
+        let markups: Vec<&str> = tokenize("<p class='a'>x<!-- c --><![CDATA[<y>]]></P>")
+            .into_iter()
+            .map(|(_, markup)| markup)
+            .collect();
+        assert_eq!(markups, vec!["<p class='a'>", "x", "<![CDATA[<y>]]>", "</P>"]);
+    }
+
+    #[test]
+    fn test_sanitize_html() {
+        assert_eq!(
+            sanitize_html(
+                r#"<p class="x" onclick="evil()">Hello <b>world</b><script>alert("x")</script></p><iframe src="https://a.com"><p>no</p></iframe>"#
+            ),
+            "<p>Hello <b>world</b></p>"
+        );
+        assert_eq!(
+            sanitize_html(r#"<a href="javascript:alert(1)" target="_blank">link</a>"#),
+            r#"<a rel="nofollow noopener">link</a>"#
+        );
+        assert_eq!(
+            sanitize_html(r#"<img src="https://a.com/i.jpg" alt="a"><img src="https://a.com/p.gif" width="1" height="1"/><img src="https://feeds.feedburner.com/~r/x/~4/y">"#),
+            r#"<img src="https://a.com/i.jpg" alt="a">"#
+        );
+        assert_eq!(sanitize_html("<div><p>unclosed <em>tags"), "<p>unclosed <em>tags</em></p>");
+        assert_eq!(
+            sanitize_html(r#"<a href="/\evil.com">a</a><a href="//evil.com">b</a><a href="/&#9;/evil.com">c</a>"#),
+            r#"<a rel="nofollow noopener">a</a><a rel="nofollow noopener">b</a><a rel="nofollow noopener">c</a>"#
+        );
+        assert_eq!(
+            sanitize_html(r#"<a href="/news?id=1&amp;page=2">same host</a>"#),
+            r#"<a href="/news?id=1&amp;page=2" rel="nofollow noopener">same host</a>"#
+        );
+        assert_eq!(sanitize_html("<p>a</b> <!-- comment -->b</p>"), "<p>a b</p>");
+        assert_eq!(sanitize_html("<![CDATA[<script>]]> 1 < 2"), "&lt;script&gt; 1 &lt; 2");
+    }
+
+    #[test]
+    fn test_is_safe_url() {
+        assert!(is_safe_url("https://example.com/a"));
+        assert!(is_safe_url(" /news/1"));
+        assert!(!is_safe_url("/\\evil.com"));
+        assert!(!is_safe_url("//evil.com"));
+        assert!(!is_safe_url("/\t/evil.com"));
+        assert!(!is_safe_url("/\n\\evil.com"));
+        assert!(!is_safe_url("java\tscript:alert(1)"));
+    }
+
+    #[test]
+    fn test_tag_attributes() {
+        let attributes = tag_attributes(
+            r#"<head><LINK rel="canonical" href='https://a.com/b'><link rel=icon href=/favicon.ico /></head>"#,
+            "link",
+        );
+        assert_eq!(attributes.len(), 2);
+        assert_eq!(attribute(&attributes[0], "href"), Some("https://a.com/b"));
+        assert_eq!(attribute(&attributes[1], "rel"), Some("icon"));
+        assert_eq!(attribute(&attributes[1], "href"), Some("/favicon.ico"));
+        assert_eq!(attribute(&attributes[1], "type"), None);
+    }
+
+    #[test]
+    fn test_to_plain_text() {
+        assert_eq!(
+            to_plain_text("<p>The wearables &#8212; to name a few of the gizmos we&#8217;ve tracked [&#8230;]</p>\n<p>© 2024 TechCrunch.</p>"),
+            "The wearables — to name a few of the gizmos we’ve tracked […] © 2024 TechCrunch."
+        );
+        assert_eq!(to_plain_text("a &amp; b &unknown; &"), "a & b &unknown; &");
+        assert_eq!(to_plain_text("<![CDATA[ 1 < 2 ]]>"), "1 < 2");
+    }
+
+    #[test]
+    fn test_excerpt() {
+        assert_eq!(excerpt("short", 10), "short");
+        assert_eq!(excerpt("a few words here", 0), "a few words here");
+        assert_eq!(excerpt("a few words here", 12), "a few words…");
+        assert_eq!(excerpt("やさしいにほんごのニュース", 5), "やさしいに…");
+    }
+}
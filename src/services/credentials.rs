@@ -0,0 +1,181 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use url::Url;
+
+use crate::{
+    entities::{
+        channel::Channel,
+        credential::{Credential, CredentialAuth},
+    },
+    error::Error,
+    utils::DBBag,
+};
+
+/// resolve_secret reads a secret value: `env:NAME` from the environment variable NAME,
+/// `file:PATH` from the (trimmed) content of the file at PATH, any other value as is.
+pub fn resolve_secret(value: &str) -> Result<String, Error> {
+    if let Some(name) = value.strip_prefix("env:") {
+        std::env::var(name).map_err(|_| Error(format!("environment variable {} is not set", name)))
+    } else if let Some(path) = value.strip_prefix("file:") {
+        std::fs::read_to_string(path)
+            .map(|content| content.trim().to_string())
+            .map_err(|err| Error(format!("could not read secret file {}: {}", path, err)))
+    } else {
+        Ok(value.to_string())
+    }
+}
+
+/// reads_secrets tells whether some values of `auth` are read from the environment or a file.
+/// Such credentials are managed by operators, right in the `secrets` collection.
+pub fn reads_secrets(auth: &CredentialAuth) -> bool {
+    let values = match auth {
+        CredentialAuth::Basic { username, password } => vec![username, password],
+        CredentialAuth::Bearer { token } => vec![token],
+        CredentialAuth::Query { value, .. } => vec![value],
+    };
+    values
+        .iter()
+        .any(|value| value.starts_with("env:") || value.starts_with("file:"))
+}
+
+/// resolve returns the credential's auth with its secret values read
+pub fn resolve(credential: &Credential) -> Result<CredentialAuth, Error> {
+    Ok(match &credential.auth {
+        CredentialAuth::Basic { username, password } => CredentialAuth::Basic {
+            username: resolve_secret(username)?,
+            password: resolve_secret(password)?,
+        },
+        CredentialAuth::Bearer { token } => CredentialAuth::Bearer {
+            token: resolve_secret(token)?,
+        },
+        CredentialAuth::Query { param, value } => CredentialAuth::Query {
+            param: param.clone(),
+            value: resolve_secret(value)?,
+        },
+    })
+}
+
+/// channel_credential finds and resolves the credential referenced by a channel, if any
+pub async fn channel_credential(
+    db_bag: &DBBag,
+    channel: &Channel,
+) -> Result<Option<CredentialAuth>, Error> {
+    let Some(name) = channel.credentials.as_deref() else {
+        return Ok(None);
+    };
+    let credential = db_bag
+        .secrets_coll
+        .get(&channel.tenant, name)
+        .await?
+        .ok_or_else(|| Error(format!("channel {} uses unknown credential {}", channel.id, name)))?;
+    resolve(&credential).map(Some)
+}
+
+/// authorized_url adds the key of query credentials to `url`, other urls are returned as is
+pub fn authorized_url(url: &str, auth: Option<&CredentialAuth>) -> Result<String, Error> {
+    match auth {
+        Some(CredentialAuth::Query { param, value }) => {
+            let mut url = Url::parse(url).map_err(|err| Error(format!("invalid url {}: {}", url, err)))?;
+            url.query_pairs_mut().append_pair(param, value);
+            Ok(url.to_string())
+        }
+        _ => Ok(url.to_string()),
+    }
+}
+
+/// strip_credential removes the key of query credentials from `url`,
+/// e.g. from a redirect target echoing it
+pub fn strip_credential(url: Url, auth: Option<&CredentialAuth>) -> Url {
+    let Some(CredentialAuth::Query { param, .. }) = auth else {
+        return url;
+    };
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .into_owned()
+        .filter(|(key, _)| key != param)
+        .collect();
+    let mut url = url;
+    url.set_query(None);
+    if !pairs.is_empty() {
+        url.query_pairs_mut().extend_pairs(pairs);
+    }
+    url
+}
+
+/// authorization returns the value of the Authorization header of basic and bearer credentials
+pub fn authorization(auth: Option<&CredentialAuth>) -> Option<String> {
+    match auth? {
+        CredentialAuth::Basic { username, password } => Some(format!(
+            "Basic {}",
+            STANDARD.encode(format!("{}:{}", username, password))
+        )),
+        CredentialAuth::Bearer { token } => Some(format!("Bearer {}", token)),
+        CredentialAuth::Query { .. } => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_secret() {
+        std::env::set_var("PATISHIE_TEST_SECRET", "s3cret");
+        assert_eq!(resolve_secret("env:PATISHIE_TEST_SECRET").unwrap(), "s3cret");
+        assert!(resolve_secret("env:PATISHIE_TEST_UNSET_SECRET").is_err());
+        assert!(resolve_secret("file:/nonexistent/secret").is_err());
+        assert_eq!(resolve_secret("plain").unwrap(), "plain");
+    }
+
+    #[test]
+    fn test_reads_secrets() {
+        assert!(reads_secrets(&CredentialAuth::Bearer {
+            token: "file:/proc/self/environ".to_string()
+        }));
+        assert!(reads_secrets(&CredentialAuth::Basic {
+            username: "user".to_string(),
+            password: "env:DB_PASSWORD".to_string(),
+        }));
+        assert!(!reads_secrets(&CredentialAuth::Query {
+            param: "env:key".to_string(),
+            value: "abc".to_string(),
+        }));
+    }
+
+    #[test]
+    fn test_authorization() {
+        let basic = CredentialAuth::Basic {
+            username: "Aladdin".to_string(),
+            password: "open sesame".to_string(),
+        };
+        assert_eq!(
+            authorization(Some(&basic)).unwrap(),
+            "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ=="
+        );
+        let bearer = CredentialAuth::Bearer {
+            token: "abc".to_string(),
+        };
+        assert_eq!(authorization(Some(&bearer)).unwrap(), "Bearer abc");
+        assert_eq!(authorization(None), None);
+    }
+
+    #[test]
+    fn test_authorized_url() {
+        let query = CredentialAuth::Query {
+            param: "api_key".to_string(),
+            value: "a&b".to_string(),
+        };
+        assert_eq!(
+            authorized_url("https://example.com/feed?lang=en", Some(&query)).unwrap(),
+            "https://example.com/feed?lang=en&api_key=a%26b"
+        );
+        assert_eq!(
+            authorized_url("https://example.com/feed", None).unwrap(),
+            "https://example.com/feed"
+        );
+        let echoed = Url::parse("https://example.com/feed?lang=en&api_key=a%26b").unwrap();
+        assert_eq!(
+            strip_credential(echoed, Some(&query)).as_str(),
+            "https://example.com/feed?lang=en"
+        );
+    }
+}
@@ -0,0 +1,262 @@
+use std::{collections::BTreeSet, time::Duration};
+
+use futures::future::join_all;
+use serde::Serialize;
+use serde_xml_rs::from_str;
+use tokio::time::timeout;
+use url::Url;
+
+use crate::{
+    entities::{rss::Rss, source_type::SourceType},
+    error::Error,
+    services::{
+        content::{attribute, tag_attributes},
+        http::{HttpClient, HttpError},
+    },
+};
+
+/// Paths probed, against the site's root, when a page links to no feed
+pub const COMMON_FEED_PATHS: &[&str] = &["/feed", "/rss.xml", "/feed.xml", "/atom.xml", "/index.xml", "/rss"];
+/// Time given to each probe of a common feed path, probes being made at once
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// FeedKind is the format of a discovered feed.
+/// Only rss feeds can be refreshed as `SourceType::RSSFeed` channels.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedKind {
+    Rss,
+    Atom,
+    Json,
+}
+
+impl FeedKind {
+    /// from_mime_type reads the `type` of a <link rel="alternate">
+    pub fn from_mime_type(mime_type: &str) -> Option<Self> {
+        match mime_type.trim().to_ascii_lowercase().as_str() {
+            "application/rss+xml" => Some(FeedKind::Rss),
+            "application/atom+xml" => Some(FeedKind::Atom),
+            "application/feed+json" | "application/json+feed" => Some(FeedKind::Json),
+            _ => None,
+        }
+    }
+}
+
+/// FeedCandidate is a feed found for a website
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FeedCandidate {
+    pub url: String,
+    pub title: Option<String>,
+    pub kind: FeedKind,
+}
+
+fn dedupe(candidates: Vec<FeedCandidate>) -> Vec<FeedCandidate> {
+    let mut seen = BTreeSet::new();
+    candidates
+        .into_iter()
+        .filter(|candidate| seen.insert(candidate.url.clone()))
+        .collect()
+}
+
+/// feed_links returns the feeds an html page declares with <link rel="alternate">, rss ones first.
+/// Relative urls are resolved against `base`.
+pub fn feed_links(html: &str, base: &str) -> Vec<FeedCandidate> {
+    let base = Url::parse(base).ok();
+    let mut candidates: Vec<FeedCandidate> = tag_attributes(html, "link")
+        .iter()
+        .filter(|attributes| {
+            attribute(attributes, "rel")
+                .map(|rel| rel.split_whitespace().any(|r| r.eq_ignore_ascii_case("alternate")))
+                .unwrap_or(false)
+        })
+        .filter_map(|attributes| {
+            let kind = FeedKind::from_mime_type(attribute(attributes, "type")?)?;
+            let href = attribute(attributes, "href")?.trim();
+            let url = match &base {
+                Some(base) => base.join(href).ok()?,
+                None => Url::parse(href).ok()?,
+            };
+            Some(FeedCandidate {
+                url: url.to_string(),
+                title: attribute(attributes, "title")
+                    .map(|title| title.trim().to_string())
+                    .filter(|title| !title.is_empty()),
+                kind,
+            })
+        })
+        .collect();
+    candidates = dedupe(candidates);
+    // stable: pages' own order is kept within a kind
+    candidates.sort_by_key(|candidate| candidate.kind != FeedKind::Rss);
+    candidates
+}
+
+/// probe_urls returns the common feed urls of the site of `url`
+pub fn probe_urls(url: &str) -> Vec<String> {
+    match Url::parse(url) {
+        Ok(url) => COMMON_FEED_PATHS
+            .iter()
+            .filter_map(|path| url.join(path).ok())
+            .map(|url| url.to_string())
+            .collect(),
+        Err(_) => vec![],
+    }
+}
+
+/// as_feed tells whether a fetched body is itself a feed, and returns it as a candidate
+pub fn as_feed(body: &str, url: &str) -> Option<FeedCandidate> {
+    let head: String = body.trim_start().chars().take(1024).collect();
+    let kind = if head.starts_with('{') && head.contains("jsonfeed.org/version") {
+        FeedKind::Json
+    } else if head.contains("<rss") {
+        FeedKind::Rss
+    } else if head.contains("<feed") {
+        FeedKind::Atom
+    } else {
+        return None;
+    };
+    let title = match kind {
+        FeedKind::Rss => from_str::<Rss>(body).ok().and_then(|rss| rss.channel.title),
+        _ => None,
+    };
+    Some(FeedCandidate {
+        url: url.to_string(),
+        title,
+        kind,
+    })
+}
+
+/// resolve_source picks what a new channel for `url` is refreshed from:
+/// its first discovered rss feed, else `url` itself, scraped by bakery,
+/// which is also used when the page cannot be fetched, e.g. when it blocks plain http clients.
+/// Returns the source's url and type, along with the feed's title.
+pub async fn resolve_source(
+    http: &HttpClient,
+    url: &str,
+) -> Result<(String, SourceType, Option<String>), Error> {
+    Url::parse(url).map_err(|err| Error(format!("invalid url {}: {}", url, err)))?;
+    let candidates = match discover(http, url).await {
+        Ok(candidates) => candidates,
+        Err(HttpError::Failed(err)) => {
+            println!("[WARN] could not discover the feeds of {}, using bakery: {}", url, err);
+            vec![]
+        }
+        Err(err) => return Err(err.into()),
+    };
+    Ok(candidates
+        .into_iter()
+        .find(|candidate| candidate.kind == FeedKind::Rss)
+        .map(|feed| (feed.url, SourceType::RSSFeed, feed.title))
+        .unwrap_or_else(|| (url.to_string(), SourceType::Bakery, None)))
+}
+
+/// discover finds the feeds of the page at `url`: the page itself when it is a feed,
+/// else the feeds it links to, else the common feed paths of its site which answer with a feed.
+pub async fn discover(http: &HttpClient, url: &str) -> Result<Vec<FeedCandidate>, HttpError> {
+    let page = http.get_page(url, None, None).await?;
+    let page_url = page.moved_to.as_deref().unwrap_or(url);
+    if let Some(feed) = as_feed(&page.body, page_url) {
+        return Ok(vec![feed]);
+    }
+    let candidates = feed_links(&page.body, page_url);
+    if !candidates.is_empty() {
+        return Ok(candidates);
+    }
+    let probe_urls = probe_urls(page_url);
+    let probes: Vec<_> = probe_urls
+        .iter()
+        .map(|probe_url| timeout(PROBE_TIMEOUT, http.get_page(probe_url, None, None)))
+        .collect();
+    let probed = join_all(probes)
+        .await
+        .into_iter()
+        .zip(&probe_urls)
+        .filter_map(|(page, probe_url)| {
+            let page = page.ok()?.ok()?;
+            as_feed(&page.body, page.moved_to.as_deref().unwrap_or(probe_url))
+        })
+        .collect();
+    Ok(dedupe(probed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HttpSettings;
+
+    #[test]
+    fn test_feed_links() {
+        let html = r#"<html><head>
+            <link rel="stylesheet" href="/style.css">
+            <link rel="alternate" type="application/atom+xml" title="Atom" href="/atom.xml">
+            <link rel="alternate" type="application/rss+xml" title=" News " href="feed/">
+            <link rel="alternate" hreflang="fr" href="/fr/">
+            <link rel="alternate" type="application/feed+json" href="https://cdn.example.com/feed.json">
+            <link rel="alternate" type="application/rss+xml" href="/blog/feed/">
+        </head></html>"#;
+        assert_eq!(
+            feed_links(html, "https://example.com/blog/"),
+            vec![
+                FeedCandidate {
+                    url: "https://example.com/blog/feed/".to_string(),
+                    title: Some("News".to_string()),
+                    kind: FeedKind::Rss,
+                },
+                FeedCandidate {
+                    url: "https://example.com/atom.xml".to_string(),
+                    title: Some("Atom".to_string()),
+                    kind: FeedKind::Atom,
+                },
+                FeedCandidate {
+                    url: "https://cdn.example.com/feed.json".to_string(),
+                    title: None,
+                    kind: FeedKind::Json,
+                },
+            ]
+        );
+        assert!(feed_links("<p>no feed</p>", "https://example.com").is_empty());
+    }
+
+    #[test]
+    fn test_probe_urls() {
+        let urls = probe_urls("https://example.com/blog/post?id=1");
+        assert_eq!(urls[0], "https://example.com/feed");
+        assert_eq!(urls[1], "https://example.com/rss.xml");
+        assert_eq!(urls.len(), COMMON_FEED_PATHS.len());
+        assert!(probe_urls("not a url").is_empty());
+    }
+
+    #[test]
+    fn test_as_feed() {
+        let rss = r#"<?xml version="1.0"?><rss version="2.0"><channel><title>Example</title>
+            <item><title>a</title><link>https://example.com/a</link></item></channel></rss>"#;
+        let feed = as_feed(rss, "https://example.com/rss.xml").unwrap();
+        assert_eq!(feed.kind, FeedKind::Rss);
+        assert_eq!(feed.title.as_deref(), Some("Example"));
+        let atom = r#"<?xml version="1.0"?><feed xmlns="http://www.w3.org/2005/Atom"></feed>"#;
+        assert_eq!(as_feed(atom, "https://example.com/atom.xml").unwrap().kind, FeedKind::Atom);
+        let json = r#"{"version": "https://jsonfeed.org/version/1.1", "items": []}"#;
+        assert_eq!(as_feed(json, "https://example.com/feed.json").unwrap().kind, FeedKind::Json);
+        assert_eq!(as_feed("<!doctype html><html></html>", "https://example.com"), None);
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_pages_resolve_to_bakery() {
+        let http = HttpClient::new(&HttpSettings {
+            user_agent: "patishie".to_string(),
+            connect_timeout_ms: 1000,
+            timeout_ms: 1000,
+            max_body_bytes: 1000,
+            proxy: None,
+            ca_bundle: None,
+            max_redirects: 1,
+            compression: false,
+        })
+        .unwrap();
+        assert_eq!(
+            resolve_source(&http, "http://127.0.0.1:1/news").await.unwrap(),
+            ("http://127.0.0.1:1/news".to_string(), SourceType::Bakery, None)
+        );
+        assert!(resolve_source(&http, "not a url").await.is_err());
+    }
+}
@@ -0,0 +1,203 @@
+use crate::{entities::potential_articles::PotentialArticle, services::content::to_plain_text};
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+// amount of chars per shingle
+const CHAR_SHINGLE: usize = 4;
+// texts with fewer normalized chars are not fingerprinted: their few shingles would collide
+const MIN_FINGERPRINT_CHARS: usize = 16;
+
+/// shingle_hash is a stable hash (fnv1a, with murmur3's finalizer to spread bits evenly),
+/// so that fingerprints stored in db remain comparable across builds
+fn shingle_hash(data: &str) -> u64 {
+    let mut hash = data
+        .bytes()
+        .fold(FNV_OFFSET, |hash, b| (hash ^ b as u64).wrapping_mul(FNV_PRIME));
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ceb9fe1a85ec53);
+    hash ^ (hash >> 33)
+}
+
+/// normalize lowercases `text`, keeping its alphanumeric chars separated by single spaces
+fn normalize(text: &str) -> Vec<char> {
+    let normalized: String = text
+        .chars()
+        .map(|c| match c.is_alphanumeric() {
+            true => c.to_lowercase().next().unwrap_or(c),
+            false => ' ',
+        })
+        .collect();
+    // chars shingles work for languages without spaces between words (e.g. japanese)
+    normalized
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .chars()
+        .collect()
+}
+
+fn shingles(text: &str) -> Vec<String> {
+    let chars = normalize(text);
+    if chars.len() < CHAR_SHINGLE {
+        return vec![chars.iter().collect()];
+    }
+    chars
+        .windows(CHAR_SHINGLE)
+        .map(|w| w.iter().collect())
+        .collect()
+}
+
+/// simhash computes a 64 bits fingerprint of `text`, built over its shingles.
+/// Close texts get fingerprints with a small hamming distance.
+pub fn simhash(text: &str) -> u64 {
+    let mut weights = [0i32; 64];
+    for shingle in shingles(text) {
+        let hash = shingle_hash(&shingle);
+        weights.iter_mut().enumerate().for_each(|(bit, w)| {
+            *w += match (hash >> bit) & 1 {
+                1 => 1,
+                _ => -1,
+            }
+        });
+    }
+    weights
+        .iter()
+        .enumerate()
+        .fold(0u64, |acc, (bit, w)| match *w > 0 {
+            true => acc | (1 << bit),
+            false => acc,
+        })
+}
+
+pub fn hamming_distance(a: i64, b: i64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// article_fingerprint is the simhash of an article's title and plain text description,
+/// stored as an i64 since bson has no unsigned integers.
+/// Articles with too little text to tell them apart get none.
+pub fn article_fingerprint(article: &PotentialArticle) -> Option<i64> {
+    let text = format!(
+        "{} {}",
+        article.title.clone().unwrap_or_default(),
+        to_plain_text(&article.desc)
+    );
+    if normalize(&text).len() < MIN_FINGERPRINT_CHARS {
+        return None;
+    }
+    Some(simhash(&text) as i64)
+}
+
+/// assign_clusters fingerprints `articles` and links each of them to the first near-duplicate
+/// found among `known` articles, then among the articles of the batch preceding it.
+/// Articles without a fingerprint are never clustered.
+/// A cluster is identified by the dedupe key of its canonical item (the first one stored).
+/// Returns the known articles which became the canonical item of a new cluster.
+pub fn assign_clusters(
+    articles: &mut [PotentialArticle],
+    known: &[PotentialArticle],
+    max_distance: u32,
+) -> Vec<PotentialArticle> {
+    let mut new_canonicals: Vec<PotentialArticle> = vec![];
+    for i in 0..articles.len() {
+        let Some(fingerprint) = article_fingerprint(&articles[i]) else {
+            articles[i].fingerprint = None;
+            continue;
+        };
+        articles[i].fingerprint = Some(fingerprint);
+        let is_near = |other: &PotentialArticle| {
+            other
+                .fingerprint
+                .map(|f| hamming_distance(f, fingerprint) <= max_distance)
+                .unwrap_or(false)
+        };
+        if let Some(canonical) = known.iter().find(|k| is_near(k)) {
+            let cluster_id = canonical.cluster_key();
+            if canonical.cluster_id.is_none()
+                && !new_canonicals.iter().any(|c| c.cluster_key() == cluster_id)
+            {
+                let mut c = canonical.clone();
+                c.cluster_id = Some(cluster_id.clone());
+                new_canonicals.push(c);
+            }
+            articles[i].cluster_id = Some(cluster_id);
+            continue;
+        }
+        let (previous, current) = articles.split_at_mut(i);
+        if let Some(canonical) = previous.iter_mut().find(|p| is_near(p)) {
+            let cluster_id = canonical.cluster_key();
+            canonical.cluster_id = Some(cluster_id.clone());
+            current[0].cluster_id = Some(cluster_id);
+        }
+    }
+    new_canonicals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn article(link: &str, title: &str, desc: &str) -> PotentialArticle {
+        PotentialArticle {
+            link: link.to_string(),
+            canonical_link: Some(link.to_string()),
+            desc: desc.to_string(),
+            title: Some(title.to_string()),
+            ..Default::default()
+        }
+    }
+
+    const DESC: &str = "<p>The central bank raised its key interest rate by a quarter point on Wednesday, citing persistent inflation in services and a tight labour market.</p>";
+
+    #[test]
+    fn test_simhash_near_duplicates() {
+        let a = article("https://a.com/1", "Central bank raises rates", DESC);
+        let b = article("https://b.com/1", "Central bank raises interest rates", DESC);
+        let c = article(
+            "https://c.com/1",
+            "Local team wins the cup",
+            "The home side clinched the title after a penalty shootout in front of a record crowd.",
+        );
+        let (fa, fb, fc) = (
+            article_fingerprint(&a).unwrap(),
+            article_fingerprint(&b).unwrap(),
+            article_fingerprint(&c).unwrap(),
+        );
+        assert!(hamming_distance(fa, fb) <= 6);
+        assert!(hamming_distance(fa, fc) > 6);
+        assert_eq!(Some(fa), article_fingerprint(&a));
+        assert_eq!(article_fingerprint(&article("https://d.com/1", "", "")), None);
+        assert_eq!(article_fingerprint(&article("https://d.com/1", "Live", "<p>ok</p>")), None);
+    }
+
+    #[test]
+    fn test_assign_clusters() {
+        let mut known = article("https://a.com/1", "Central bank raises rates", DESC);
+        known.fingerprint = article_fingerprint(&known);
+        let mut batch = vec![
+            article("https://b.com/1", "Central bank raises interest rates", DESC),
+            article("https://c.com/1", "Local team wins the cup", "Penalty shootout."),
+            article("https://d.com/1", "Local team wins the cup!", "Penalty shootout."),
+        ];
+        let new_canonicals = assign_clusters(&mut batch, &[known], 6);
+        assert_eq!(new_canonicals.len(), 1);
+        assert_eq!(new_canonicals[0].cluster_id.as_deref(), Some("https://a.com/1"));
+        assert_eq!(batch[0].cluster_id.as_deref(), Some("https://a.com/1"));
+        assert_eq!(batch[1].cluster_id.as_deref(), Some("https://c.com/1"));
+        assert_eq!(batch[2].cluster_id.as_deref(), Some("https://c.com/1"));
+    }
+
+    #[test]
+    fn test_short_articles_are_not_clustered() {
+        let mut known = article("https://a.com/1", "", "");
+        known.fingerprint = Some(simhash(" ") as i64);
+        let mut batch = vec![
+            article("https://b.com/1", "", ""),
+            article("https://c.com/1", "", "<img src=\"https://c.com/i.jpg\">"),
+        ];
+        assert!(assign_clusters(&mut batch, &[known], 6).is_empty());
+        assert!(batch.iter().all(|a| a.fingerprint.is_none() && a.cluster_id.is_none()));
+    }
+}
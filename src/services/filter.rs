@@ -0,0 +1,185 @@
+use regex::Regex;
+use serde::Serialize;
+
+use crate::{
+    entities::{
+        filter_rule::{FilterAction, FilterField, FilterRule},
+        potential_articles::PotentialArticle,
+    },
+    error::Error,
+};
+
+#[derive(Debug, Clone)]
+pub struct CompiledRule {
+    pub rule: FilterRule,
+    regex: Regex,
+}
+
+impl CompiledRule {
+    pub fn new(rule: &FilterRule) -> Result<Self, Error> {
+        Regex::new(&rule.pattern)
+            .map(|regex| CompiledRule {
+                rule: rule.clone(),
+                regex,
+            })
+            .map_err(|err| Error(format!("invalid filter pattern {}: {}", rule.pattern, err)))
+    }
+
+    pub fn matches(&self, article: &PotentialArticle) -> bool {
+        match self.rule.field {
+            FilterField::Title => self.regex.is_match(article.title.as_deref().unwrap_or_default()),
+            FilterField::Description => self.regex.is_match(&article.desc),
+            FilterField::Link => self.regex.is_match(&article.link),
+            FilterField::Category => article
+                .categories
+                .iter()
+                .flatten()
+                .any(|category| self.regex.is_match(category)),
+        }
+    }
+}
+
+/// compile_rules compiles every rule, failing on the first invalid pattern
+pub fn compile_rules(rules: &[FilterRule]) -> Result<Vec<CompiledRule>, Error> {
+    rules.iter().map(CompiledRule::new).collect()
+}
+
+/// FilterVerdict tells whether an article is kept, and otherwise the rule which dropped it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FilterVerdict {
+    pub kept: bool,
+    pub rule: Option<FilterRule>,
+}
+
+/// verdict applies `rules` to an `article`: it is dropped by the first matching exclude rule,
+/// and, when there are include rules, if none of them matches.
+pub fn verdict(rules: &[CompiledRule], article: &PotentialArticle) -> FilterVerdict {
+    if let Some(excluded) = rules
+        .iter()
+        .find(|r| r.rule.action == FilterAction::Exclude && r.matches(article))
+    {
+        return FilterVerdict {
+            kept: false,
+            rule: Some(excluded.rule.clone()),
+        };
+    }
+    let mut includes = rules
+        .iter()
+        .filter(|r| r.rule.action == FilterAction::Include)
+        .peekable();
+    if includes.peek().is_none() {
+        return FilterVerdict {
+            kept: true,
+            rule: None,
+        };
+    }
+    match includes.find(|r| r.matches(article)) {
+        Some(included) => FilterVerdict {
+            kept: true,
+            rule: Some(included.rule.clone()),
+        },
+        None => FilterVerdict {
+            kept: false,
+            rule: None,
+        },
+    }
+}
+
+/// PreviewItem is an article of a dry-run preview, with the verdict of the rules tested
+#[derive(Debug, Clone, Serialize)]
+pub struct PreviewItem {
+    pub item: PotentialArticle,
+    pub filter: FilterVerdict,
+}
+
+pub fn preview(articles: Vec<PotentialArticle>, rules: &[CompiledRule]) -> Vec<PreviewItem> {
+    articles
+        .into_iter()
+        .map(|item| PreviewItem {
+            filter: verdict(rules, &item),
+            item,
+        })
+        .collect()
+}
+
+/// apply_filters returns the `articles` kept by `rules`, and the number of filtered ones
+pub fn apply_filters(
+    articles: Vec<PotentialArticle>,
+    rules: &[CompiledRule],
+) -> (Vec<PotentialArticle>, usize) {
+    let total = articles.len();
+    let kept: Vec<PotentialArticle> = articles
+        .into_iter()
+        .filter(|article| verdict(rules, article).kept)
+        .collect();
+    let filtered = total - kept.len();
+    (kept, filtered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn article(title: &str, link: &str, categories: &[&str]) -> PotentialArticle {
+        PotentialArticle {
+            link: link.to_string(),
+            title: Some(title.to_string()),
+            categories: Some(categories.iter().map(|c| c.to_string()).collect()),
+            ..Default::default()
+        }
+    }
+
+    fn rule(action: FilterAction, field: FilterField, pattern: &str) -> FilterRule {
+        FilterRule {
+            action,
+            field,
+            pattern: pattern.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_exclude_rules() {
+        let rules = compile_rules(&[
+            rule(FilterAction::Exclude, FilterField::Title, "(?i)^sponsored"),
+            rule(FilterAction::Exclude, FilterField::Category, "^Deals$"),
+        ])
+        .unwrap();
+        let (kept, filtered) = apply_filters(
+            vec![
+                article("Sponsored: a new phone", "https://a.com/1", &[]),
+                article("Best laptops", "https://a.com/2", &["Deals", "Tech"]),
+                article("Rates are up", "https://a.com/3", &["Economy"]),
+            ],
+            &rules,
+        );
+        assert_eq!(filtered, 2);
+        assert_eq!(kept[0].link, "https://a.com/3");
+    }
+
+    #[test]
+    fn test_include_rules() {
+        let rules = compile_rules(&[
+            rule(FilterAction::Include, FilterField::Link, "/tech/"),
+            rule(FilterAction::Exclude, FilterField::Title, "rumor"),
+        ])
+        .unwrap();
+        let tech = article("New chip", "https://a.com/tech/1", &[]);
+        let rumor = article("Phone rumor", "https://a.com/tech/2", &[]);
+        let sports = article("Cup final", "https://a.com/sports/1", &[]);
+        assert!(verdict(&rules, &tech).kept);
+        assert_eq!(verdict(&rules, &rumor).rule, Some(rules[1].rule.clone()));
+        assert_eq!(
+            verdict(&rules, &sports),
+            FilterVerdict {
+                kept: false,
+                rule: None
+            }
+        );
+        assert!(verdict(&[], &sports).kept);
+    }
+
+    #[test]
+    fn test_invalid_rule() {
+        assert!(compile_rules(&[rule(FilterAction::Exclude, FilterField::Title, "(")]).is_err());
+    }
+}
@@ -0,0 +1,352 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use chrono::{DateTime, Utc};
+use reqwest::{
+    header::{
+        HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CACHE_CONTROL, CONTENT_TYPE, LOCATION,
+        RETRY_AFTER,
+    },
+    redirect::Policy,
+    Certificate, Client, ClientBuilder, Proxy, Response, StatusCode,
+};
+use url::Url;
+
+use crate::{
+    config::HttpSettings,
+    entities::{credential::CredentialAuth, detected_encoding::DetectedEncoding},
+    error::Error,
+    services::{
+        charset::transcode,
+        credentials::{authorization, authorized_url, strip_credential},
+    },
+};
+
+/// client_builder sets up a reqwest client from the shared http settings
+pub fn client_builder(settings: &HttpSettings) -> Result<ClientBuilder, Error> {
+    let mut builder = Client::builder()
+        .user_agent(&settings.user_agent)
+        .connect_timeout(Duration::from_millis(settings.connect_timeout_ms))
+        .timeout(Duration::from_millis(settings.timeout_ms))
+        .redirect(Policy::limited(settings.max_redirects))
+        .gzip(settings.compression)
+        .brotli(settings.compression)
+        .deflate(settings.compression);
+    if let Some(proxy) = settings.proxy.as_deref().filter(|p| !p.is_empty()) {
+        builder = builder.proxy(
+            Proxy::all(proxy).map_err(|err| Error(format!("invalid proxy {}: {}", proxy, err)))?,
+        );
+    }
+    if let Some(path) = settings.ca_bundle.as_deref().filter(|p| !p.is_empty()) {
+        let pem = std::fs::read(path).map_err(|err| Error(format!("could not read {}: {}", path, err)))?;
+        let certificates = Certificate::from_pem_bundle(&pem)
+            .map_err(|err| Error(format!("invalid certificates in {}: {}", path, err)))?;
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    Ok(builder)
+}
+
+/// to_header_map turns per-channel headers into request headers
+pub fn to_header_map(headers: &BTreeMap<String, String>) -> Result<HeaderMap, Error> {
+    let mut map = HeaderMap::new();
+    for (name, value) in headers {
+        let name = HeaderName::from_bytes(name.trim().as_bytes())
+            .map_err(|err| Error(format!("invalid header name {}: {}", name, err)))?;
+        let value = HeaderValue::from_str(value.trim())
+            .map_err(|err| Error(format!("invalid value of header {}: {}", name, err)))?;
+        map.insert(name, value);
+    }
+    Ok(map)
+}
+
+/// HttpError tells a gone resource from other failures of a request
+#[derive(Debug, Clone, PartialEq)]
+pub enum HttpError {
+    // 410 Gone
+    Gone(String),
+    // failures which came with a Retry-After delay (ms)
+    RetryAfter(String, i64),
+    Failed(String),
+}
+
+impl From<Error> for HttpError {
+    fn from(err: Error) -> Self {
+        HttpError::Failed(err.0)
+    }
+}
+
+impl From<HttpError> for Error {
+    fn from(err: HttpError) -> Self {
+        match err {
+            HttpError::Gone(url) => Error(format!("{} is gone", url)),
+            HttpError::RetryAfter(err, delay) => Error(format!("{}, retry after {}ms", err, delay)),
+            HttpError::Failed(err) => Error(err),
+        }
+    }
+}
+
+/// Page is the body of a fetched url, transcoded to utf-8
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page {
+    pub body: String,
+    // the encoding the body was transcoded from
+    pub encoding: DetectedEncoding,
+    // url reached through permanent redirects (301, 308) only, if any
+    pub moved_to: Option<String>,
+    // Cache-Control max-age and Retry-After of the response, in ms
+    pub max_age: Option<i64>,
+    pub retry_after: Option<i64>,
+}
+
+/// max_age reads the max-age of a Cache-Control header, in ms
+pub fn max_age(cache_control: &str) -> Option<i64> {
+    cache_control.split(',').find_map(|directive| {
+        let (name, value) = directive.split_once('=')?;
+        if !name.trim().eq_ignore_ascii_case("max-age") {
+            return None;
+        }
+        value.trim().trim_matches('"').parse::<i64>().ok().map(|seconds| seconds.max(0).saturating_mul(1000))
+    })
+}
+
+/// retry_after reads a Retry-After header, either seconds or an http date, as a delay from `now` in ms
+pub fn retry_after(value: &str, now: i64) -> Option<i64> {
+    let value = value.trim();
+    match value.parse::<i64>() {
+        Ok(seconds) => Some(seconds.max(0).saturating_mul(1000)),
+        Err(_) => DateTime::parse_from_rfc2822(value)
+            .ok()
+            .map(|date| (date.timestamp_millis() - now).max(0)),
+    }
+}
+
+fn header_hint(response: &Response, name: HeaderName, parse: impl Fn(&str) -> Option<i64>) -> Option<i64> {
+    response
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse)
+}
+
+/// is_permanent tells whether a redirect status means the resource moved for good
+pub fn is_permanent(status: StatusCode) -> bool {
+    status == StatusCode::MOVED_PERMANENTLY || status == StatusCode::PERMANENT_REDIRECT
+}
+
+/// sends_secrets tells whether the per-channel headers and credential of a request to `origin`
+/// are sent to `url`, where it was redirected: only when it is on the same host.
+/// Sending them in clear to the host of an https `origin` is an error.
+fn sends_secrets(origin: &Url, url: &Url) -> Result<bool, HttpError> {
+    if url.host_str() != origin.host_str() {
+        return Ok(false);
+    }
+    if origin.scheme() == "https" && url.scheme() != "https" {
+        return Err(HttpError::Failed(format!("{} redirected to the insecure {}", origin, url)));
+    }
+    Ok(true)
+}
+
+/// HttpClient is the client shared by every fetcher of feeds and pages.
+/// It follows redirects itself, to tell permanent moves from temporary ones.
+#[derive(Debug)]
+pub struct HttpClient {
+    client: Client,
+    max_body_bytes: usize,
+    max_redirects: usize,
+}
+
+impl HttpClient {
+    pub fn new(settings: &HttpSettings) -> Result<Self, Error> {
+        Ok(HttpClient {
+            client: client_builder(settings)?
+                .redirect(Policy::none())
+                .build()
+                .map_err(|err| Error(err.to_string()))?,
+            max_body_bytes: settings.max_body_bytes,
+            max_redirects: settings.max_redirects,
+        })
+    }
+
+    /// get_text fetches `url` with some extra `headers` and an optional credential,
+    /// and returns its decoded body.
+    pub async fn get_text(
+        &self,
+        url: &str,
+        headers: Option<&BTreeMap<String, String>>,
+        auth: Option<&CredentialAuth>,
+    ) -> Result<String, Error> {
+        self.get_page(url, headers, auth)
+            .await
+            .map(|page| page.body)
+            .map_err(Error::from)
+    }
+
+    /// get_page fetches `url` with some extra `headers` and an optional credential, following redirects.
+    /// The headers and credential are only sent to the host of `url`, see `sends_secrets`.
+    /// Non-2xx statuses and bodies larger than the configured max are errors.
+    pub async fn get_page(
+        &self,
+        url: &str,
+        headers: Option<&BTreeMap<String, String>>,
+        auth: Option<&CredentialAuth>,
+    ) -> Result<Page, HttpError> {
+        let origin = Url::parse(url).map_err(|err| HttpError::Failed(format!("invalid url {}: {}", url, err)))?;
+        let headers = headers.map(to_header_map).transpose()?.unwrap_or_default();
+        let mut current = origin.clone();
+        let mut permanent = true;
+        let has_secrets = auth.is_some() || !headers.is_empty();
+        for _ in 0..=self.max_redirects {
+            let secrets = has_secrets && sends_secrets(&origin, &current)?;
+            let auth = auth.filter(|_| secrets);
+            let mut request_headers = match secrets {
+                true => headers.clone(),
+                false => HeaderMap::new(),
+            };
+            if let Some(authorization) = authorization(auth) {
+                request_headers.insert(
+                    AUTHORIZATION,
+                    HeaderValue::from_str(&authorization)
+                        .map_err(|_| HttpError::Failed(format!("invalid credential for {}", url)))?,
+                );
+            }
+            // errors never print the authorized url, which may hold a key
+            let response = self
+                .client
+                .get(authorized_url(current.as_str(), auth)?)
+                .headers(request_headers)
+                .send()
+                .await
+                .map_err(|err| HttpError::Failed(format!("{}: {}", current, err.without_url())))?;
+            let status = response.status();
+            if status.is_redirection() {
+                let location = response
+                    .headers()
+                    .get(LOCATION)
+                    .and_then(|location| location.to_str().ok())
+                    .ok_or_else(|| HttpError::Failed(format!("{} answered {} without location", current, status)))?;
+                let next = current
+                    .join(location)
+                    .map_err(|err| HttpError::Failed(format!("invalid redirect of {}: {}", current, err)))?;
+                permanent &= is_permanent(status);
+                current = strip_credential(next, auth);
+                continue;
+            }
+            if status == StatusCode::GONE {
+                return Err(HttpError::Gone(current.to_string()));
+            }
+            let now = Utc::now().timestamp_millis();
+            let retry_after = header_hint(&response, RETRY_AFTER, |value| retry_after(value, now));
+            if !status.is_success() {
+                let err = format!("{} answered {}", current, status);
+                return Err(match retry_after {
+                    Some(delay) => HttpError::RetryAfter(err, delay),
+                    None => HttpError::Failed(err),
+                });
+            }
+            let max_age = header_hint(&response, CACHE_CONTROL, max_age);
+            let (body, encoding) = self.read_body(response, current.as_str()).await?;
+            return Ok(Page {
+                body,
+                encoding,
+                moved_to: (permanent && current != origin).then(|| current.to_string()),
+                max_age,
+                retry_after,
+            });
+        }
+        Err(HttpError::Failed(format!("{} redirected more than {} times", url, self.max_redirects)))
+    }
+
+    /// post_form posts a form to `url`, any non-2xx answer is an error
+    pub async fn post_form(&self, url: &str, form: &[(&str, &str)]) -> Result<(), Error> {
+        let response = self
+            .client
+            .post(url)
+            .form(form)
+            .send()
+            .await
+            .map_err(|err| Error(format!("{}: {}", url, err.without_url())))?;
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            status => Err(Error(format!("{} answered {}", url, status))),
+        }
+    }
+
+    async fn read_body(&self, mut response: Response, url: &str) -> Result<(String, DetectedEncoding), Error> {
+        let too_large = || Error(format!("{} is larger than {} bytes", url, self.max_body_bytes));
+        if response.content_length().unwrap_or(0) as usize > self.max_body_bytes {
+            return Err(too_large());
+        }
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|ct| ct.to_str().ok())
+            .map(|ct| ct.to_string());
+        let mut body: Vec<u8> = vec![];
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|err| Error(format!("{}: {}", url, err.without_url())))?
+        {
+            if body.len() + chunk.len() > self.max_body_bytes {
+                return Err(too_large());
+            }
+            body.extend_from_slice(&chunk);
+        }
+        Ok(transcode(&body, content_type.as_deref()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_header_map() {
+        let headers = BTreeMap::from([
+            ("Cookie".to_string(), "session=abc".to_string()),
+            ("Authorization".to_string(), " Bearer token ".to_string()),
+        ]);
+        let map = to_header_map(&headers).unwrap();
+        assert_eq!(map.get("cookie").unwrap(), "session=abc");
+        assert_eq!(map.get("authorization").unwrap(), "Bearer token");
+        assert!(to_header_map(&BTreeMap::from([("bad name".to_string(), "x".to_string())])).is_err());
+    }
+
+    #[test]
+    fn test_max_age() {
+        assert_eq!(max_age("public, max-age=600"), Some(600_000));
+        assert_eq!(max_age("Max-Age=\"60\", must-revalidate"), Some(60_000));
+        assert_eq!(max_age("no-cache"), None);
+        assert_eq!(max_age("max-age=9223372036854775807"), Some(i64::MAX));
+    }
+
+    #[test]
+    fn test_retry_after() {
+        assert_eq!(retry_after("120", 0), Some(120_000));
+        assert_eq!(retry_after("9223372036854775807", 0), Some(i64::MAX));
+        let now = DateTime::parse_from_rfc3339("2015-10-21T07:27:00Z").unwrap().timestamp_millis();
+        assert_eq!(retry_after("Wed, 21 Oct 2015 07:28:00 GMT", now), Some(60_000));
+        assert_eq!(retry_after("Wed, 21 Oct 2015 07:28:00 GMT", now + 120_000), Some(0));
+        assert_eq!(retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn test_sends_secrets() {
+        let url = |url: &str| Url::parse(url).unwrap();
+        let origin = url("https://example.com/feed");
+        assert!(sends_secrets(&origin, &url("https://example.com/rss")).unwrap());
+        assert!(!sends_secrets(&origin, &url("https://evil.com/feed")).unwrap());
+        assert!(!sends_secrets(&origin, &url("http://evil.com/feed")).unwrap());
+        assert!(sends_secrets(&origin, &url("http://example.com/feed")).is_err());
+        assert!(sends_secrets(&url("http://example.com/feed"), &url("http://example.com/rss")).unwrap());
+    }
+
+    #[test]
+    fn test_is_permanent() {
+        assert!(is_permanent(StatusCode::MOVED_PERMANENTLY));
+        assert!(is_permanent(StatusCode::PERMANENT_REDIRECT));
+        assert!(!is_permanent(StatusCode::FOUND));
+        assert!(!is_permanent(StatusCode::TEMPORARY_REDIRECT));
+    }
+}
@@ -0,0 +1,290 @@
+use crate::{
+    entities::{
+        potential_articles::{PotentialArticle, RubyAnnotation},
+        text_normalization::{RubyMode, TextNormalization},
+    },
+    services::content::{to_plain_text, tokenize, Token},
+};
+
+const HALF_WIDTH_KANA: &str = "｡｢｣､･ｦｧｨｩｪｫｬｭｮｯｰｱｲｳｴｵｶｷｸｹｺｻｼｽｾｿﾀﾁﾂﾃﾄﾅﾆﾇﾈﾉﾊﾋﾌﾍﾎﾏﾐﾑﾒﾓﾔﾕﾖﾗﾘﾙﾚﾛﾜﾝ";
+const FULL_WIDTH_KANA: &str = "。「」、・ヲァィゥェォャュョッーアイウエオカキクケコサシスセソタチツテトナニヌネノハヒフヘホマミムメモヤユヨラリルレロワン";
+// kana taking a voiced sound mark (dakuten), and those also taking a semi-voiced one (handakuten)
+const DAKUTEN_KANA: &str = "カキクケコサシスセソタチツテトハヒフヘホ";
+const HANDAKUTEN_KANA: &str = "ハヒフヘホ";
+const HALF_WIDTH_DAKUTEN: char = 'ﾞ';
+const HALF_WIDTH_HANDAKUTEN: char = 'ﾟ';
+
+/// Tags of <ruby> elements kept, without their markup, by `strip_ruby`
+const RUBY_TAGS: &[&str] = &["ruby", "rb", "rtc"];
+/// Tags holding the annotations of a <ruby> element, which closing tag may be omitted
+const ANNOTATION_TAGS: &[&str] = &["rt", "rp"];
+
+/// has_ruby tells whether `html` holds a <ruby> element
+fn has_ruby(html: &str) -> bool {
+    tokenize(html)
+        .iter()
+        .any(|(token, _)| matches!(token, Token::Open { name, .. } if name == "ruby"))
+}
+
+/// strip_ruby removes the annotations (<rt>, <rp>) of the <ruby> elements of `html`, keeping the annotated text
+pub fn strip_ruby(html: &str) -> String {
+    let mut res = String::with_capacity(html.len());
+    let mut in_annotation = false;
+    for (token, markup) in tokenize(html) {
+        match token {
+            Token::Open { name, .. } if ANNOTATION_TAGS.contains(&name.as_str()) => in_annotation = true,
+            Token::Close(name) if ANNOTATION_TAGS.contains(&name.as_str()) => in_annotation = false,
+            Token::Open { name, .. } if RUBY_TAGS.contains(&name.as_str()) => {}
+            Token::Close(name) if RUBY_TAGS.contains(&name.as_str()) => in_annotation = false,
+            _ if in_annotation => {}
+            _ => res.push_str(markup),
+        }
+    }
+    res
+}
+
+/// ruby_annotations returns the annotated texts of `html` with their reading
+pub fn ruby_annotations(html: &str) -> Vec<RubyAnnotation> {
+    let mut annotations = vec![];
+    let mut in_ruby = false;
+    // markup of the annotated text, and of its reading while inside a <rt>
+    let mut base = String::new();
+    let mut reading: Option<String> = None;
+    let mut in_rp = false;
+    let mut flush = |base: &mut String, reading: &mut Option<String>| {
+        if let Some(reading) = reading.take() {
+            let annotation = RubyAnnotation {
+                base: to_plain_text(base),
+                reading: to_plain_text(&reading),
+            };
+            if !annotation.base.is_empty() && !annotation.reading.is_empty() {
+                annotations.push(annotation);
+            }
+            base.clear();
+        }
+    };
+    for (token, markup) in tokenize(html) {
+        match token {
+            Token::Open { name, .. } if name == "ruby" => {
+                in_ruby = true;
+                base.clear();
+            }
+            _ if !in_ruby => {}
+            Token::Close(name) if name == "ruby" => {
+                flush(&mut base, &mut reading);
+                in_ruby = false;
+                in_rp = false;
+            }
+            Token::Open { name, .. } if name == "rt" => {
+                flush(&mut base, &mut reading);
+                reading = Some(String::new());
+                in_rp = false;
+            }
+            Token::Close(name) if name == "rt" => flush(&mut base, &mut reading),
+            Token::Open { name, .. } if name == "rp" => {
+                flush(&mut base, &mut reading);
+                in_rp = true;
+            }
+            Token::Close(name) if name == "rp" => in_rp = false,
+            _ if in_rp => {}
+            _ => match reading.as_mut() {
+                Some(reading) => reading.push_str(markup),
+                None => base.push_str(markup),
+            },
+        }
+    }
+    annotations
+}
+
+fn compose_sound_mark(kana: char, mark: char) -> Option<char> {
+    match mark {
+        HALF_WIDTH_DAKUTEN if kana == 'ウ' => Some('ヴ'),
+        HALF_WIDTH_DAKUTEN if DAKUTEN_KANA.contains(kana) => char::from_u32(kana as u32 + 1),
+        HALF_WIDTH_HANDAKUTEN if HANDAKUTEN_KANA.contains(kana) => char::from_u32(kana as u32 + 2),
+        _ => None,
+    }
+}
+
+/// normalize_width turns full-width ascii characters and ideographic spaces into ascii ones,
+/// and half-width katakana into full-width ones, composing their sound marks.
+pub fn normalize_width(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\u{3000}' => res.push(' '),
+            '\u{FF01}'..='\u{FF5E}' => res.push(char::from_u32(c as u32 - 0xFEE0).unwrap_or(c)),
+            HALF_WIDTH_DAKUTEN | HALF_WIDTH_HANDAKUTEN => {
+                match res.chars().last().and_then(|last| compose_sound_mark(last, c)) {
+                    Some(composed) => {
+                        res.pop();
+                        res.push(composed);
+                    }
+                    None if c == HALF_WIDTH_DAKUTEN => res.push('゛'),
+                    None => res.push('゜'),
+                }
+            }
+            _ => match HALF_WIDTH_KANA.chars().position(|k| k == c) {
+                Some(i) => res.push(FULL_WIDTH_KANA.chars().nth(i).unwrap_or(c)),
+                None => res.push(c),
+            },
+        }
+    }
+    res
+}
+
+/// normalize_html_width applies `normalize_width` to the text of `html`, leaving its tags untouched.
+/// Characters becoming markup (e.g. "＜") are escaped.
+pub fn normalize_html_width(html: &str) -> String {
+    let mut res = String::with_capacity(html.len());
+    let mut text = String::new();
+    let mut in_tag = false;
+    let mut quote: Option<char> = None;
+    let flush = |text: &mut String, res: &mut String| {
+        let escaped = text
+            .replace('＆', "&amp;")
+            .replace('＜', "&lt;")
+            .replace('＞', "&gt;");
+        res.push_str(&normalize_width(&escaped));
+        text.clear();
+    };
+    for c in html.chars() {
+        match (in_tag, quote, c) {
+            (false, _, '<') => {
+                flush(&mut text, &mut res);
+                in_tag = true;
+                res.push(c);
+            }
+            (false, _, _) => text.push(c),
+            (true, None, '>') => {
+                in_tag = false;
+                res.push(c);
+            }
+            (true, None, '"' | '\'') => {
+                quote = Some(c);
+                res.push(c);
+            }
+            (true, Some(q), _) if q == c => {
+                quote = None;
+                res.push(c);
+            }
+            (true, _, _) => res.push(c),
+        }
+    }
+    flush(&mut text, &mut res);
+    res
+}
+
+/// normalize_article handles the ruby annotations and character widths of an article,
+/// and fills its reading-friendly plain text, free of annotations.
+pub fn normalize_article(article: &mut PotentialArticle, normalization: &TextNormalization) {
+    if normalization.ruby == RubyMode::Separate {
+        let mut annotations = ruby_annotations(article.title.as_deref().unwrap_or_default());
+        annotations.extend(ruby_annotations(&article.desc));
+        article.ruby = Some(annotations).filter(|a| !a.is_empty());
+    }
+    let width = |text: String| match normalization.width {
+        true => normalize_html_width(&text),
+        false => text,
+    };
+    let reading = to_plain_text(&strip_ruby(&article.desc));
+    article.reading = Some(match normalization.width {
+        true => normalize_width(&reading),
+        false => reading,
+    });
+    if normalization.ruby != RubyMode::Keep {
+        article.title = article.title.as_deref().map(|title| match has_ruby(title) {
+            true => to_plain_text(&strip_ruby(title)),
+            false => title.to_string(),
+        });
+        article.desc = strip_ruby(&article.desc);
+        article.content = article.content.as_deref().map(strip_ruby);
+    }
+    if normalization.width {
+        article.title = article.title.take().map(|title| normalize_width(&title));
+        article.desc = width(std::mem::take(&mut article.desc));
+        article.content = article.content.take().map(width);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NHK_DESC: &str = "<p><ruby>東京<rt>とうきょう</rt></ruby>で<ruby><rb>新</rb><rp>(</rp><rt>あたら</rt><rp>)</rp></ruby>しい<ruby>美術館<rt>びじゅつかん</rt></ruby>ができました</p>";
+
+    #[test]
+    fn test_strip_ruby() {
+        assert_eq!(strip_ruby(NHK_DESC), "<p>東京で新しい美術館ができました</p>");
+        assert_eq!(strip_ruby("<ruby>漢字<rt>かんじ</ruby>"), "漢字");
+        assert_eq!(
+            strip_ruby("<RUBY>漢<RT>かん</RT>字<rt>じ<rt>ど</ruby> <b>ok</b>"),
+            "漢字 <b>ok</b>"
+        );
+    }
+
+    #[test]
+    fn test_ruby_annotations() {
+        let readings: Vec<(String, String)> = ruby_annotations(NHK_DESC)
+            .into_iter()
+            .map(|a| (a.base, a.reading))
+            .collect();
+        assert_eq!(
+            readings,
+            vec![
+                ("東京".to_string(), "とうきょう".to_string()),
+                ("新".to_string(), "あたら".to_string()),
+                ("美術館".to_string(), "びじゅつかん".to_string()),
+            ]
+        );
+        assert_eq!(
+            ruby_annotations("<ruby>明日<rt>あ</rt>日<rt>す</rt></ruby>").len(),
+            2
+        );
+        assert_eq!(
+            ruby_annotations("<ruby><b>漢字</b><rp>(</rp><rt>かんじ</ruby>"),
+            vec![RubyAnnotation {
+                base: "漢字".to_string(),
+                reading: "かんじ".to_string(),
+            }]
+        );
+        assert_eq!(ruby_annotations("漢字<rt>かんじ</rt>"), vec![]);
+    }
+
+    #[test]
+    fn test_normalize_width() {
+        assert_eq!(normalize_width("ＮＨＫ　ニュース２０２４！"), "NHK ニュース2024!");
+        assert_eq!(normalize_width("ｶﾞｲﾄﾞﾌﾞｯｸ｡ﾊﾟﾝ ｳﾞｧ"), "ガイドブック。パン ヴァ");
+        assert_eq!(normalize_width("ﾞｱﾟ"), "゛ア゜");
+        assert_eq!(
+            normalize_html_width(r#"<a href="/ｱ">ＡＢ＜Ｃ＞&amp;＆</a>"#),
+            r#"<a href="/ｱ">AB&lt;C&gt;&amp;&amp;</a>"#
+        );
+    }
+
+    #[test]
+    fn test_normalize_article() {
+        let article = PotentialArticle {
+            title: Some("<ruby>美術館<rt>びじゅつかん</rt></ruby>がオープン　２０２４".to_string()),
+            desc: NHK_DESC.to_string(),
+            ..Default::default()
+        };
+        let mut separated = article.clone();
+        normalize_article(
+            &mut separated,
+            &TextNormalization {
+                ruby: RubyMode::Separate,
+                width: true,
+            },
+        );
+        assert_eq!(separated.title.as_deref(), Some("美術館がオープン 2024"));
+        assert_eq!(separated.desc, "<p>東京で新しい美術館ができました</p>");
+        assert_eq!(separated.reading.as_deref(), Some("東京で新しい美術館ができました"));
+        assert_eq!(separated.ruby.map(|r| r.len()), Some(4));
+
+        let mut kept = article.clone();
+        normalize_article(&mut kept, &TextNormalization::default());
+        assert_eq!(kept.desc, NHK_DESC);
+        assert_eq!(kept.ruby, None);
+        assert_eq!(kept.reading.as_deref(), Some("東京で新しい美術館ができました"));
+    }
+}
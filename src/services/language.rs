@@ -0,0 +1,146 @@
+use whatlang::{detect, Lang};
+
+use crate::{entities::potential_articles::PotentialArticle, services::content::to_plain_text};
+
+// ISO 639-3 codes of whatlang's languages, with their ISO 639-1 counterpart
+const ISO_639_1: &[(&str, &str)] = &[
+    ("afr", "af"), ("aka", "ak"), ("amh", "am"), ("ara", "ar"), ("aze", "az"), ("bel", "be"),
+    ("ben", "bn"), ("bul", "bg"), ("cat", "ca"), ("ces", "cs"), ("cmn", "zh"), ("dan", "da"),
+    ("deu", "de"), ("ell", "el"), ("eng", "en"), ("epo", "eo"), ("est", "et"), ("fin", "fi"),
+    ("fra", "fr"), ("guj", "gu"), ("heb", "he"), ("hin", "hi"), ("hrv", "hr"), ("hun", "hu"),
+    ("hye", "hy"), ("ind", "id"), ("ita", "it"), ("jav", "jv"), ("jpn", "ja"), ("kan", "kn"),
+    ("kat", "ka"), ("khm", "km"), ("kor", "ko"), ("lat", "la"), ("lav", "lv"), ("lit", "lt"),
+    ("mal", "ml"), ("mar", "mr"), ("mkd", "mk"), ("mya", "my"), ("nep", "ne"), ("nld", "nl"),
+    ("nob", "nb"), ("ori", "or"), ("pan", "pa"), ("pes", "fa"), ("pol", "pl"), ("por", "pt"),
+    ("ron", "ro"), ("rus", "ru"), ("sin", "si"), ("slk", "sk"), ("slv", "sl"), ("sna", "sn"),
+    ("spa", "es"), ("srp", "sr"), ("swe", "sv"), ("tam", "ta"), ("tel", "te"), ("tgl", "tl"),
+    ("tha", "th"), ("tuk", "tk"), ("tur", "tr"), ("ukr", "uk"), ("urd", "ur"), ("uzb", "uz"),
+    ("vie", "vi"), ("yid", "yi"), ("zul", "zu"),
+];
+
+fn lang_code(lang: Lang) -> String {
+    ISO_639_1
+        .iter()
+        .find(|(iso3, _)| *iso3 == lang.code())
+        .map(|(_, iso1)| iso1.to_string())
+        .unwrap_or_else(|| lang.code().to_string())
+}
+
+/// normalize_language turns a declared language tag (e.g. "en-US", "ja_JP") into its lowercased primary subtag
+pub fn normalize_language(declared: &str) -> Option<String> {
+    declared
+        .trim()
+        .split(['-', '_'])
+        .next()
+        .map(|primary| primary.to_lowercase())
+        .filter(|primary| (2..=3).contains(&primary.len()) && primary.chars().all(|c| c.is_ascii_alphabetic()))
+}
+
+/// detect_language guesses the language of `text`, if the guess' confidence reaches `min_confidence`
+pub fn detect_language(text: &str, min_confidence: f64) -> Option<String> {
+    detect(text)
+        .filter(|info| info.confidence() >= min_confidence)
+        .map(|info| lang_code(info.lang()))
+}
+
+/// article_language returns the declared language of an article,
+/// or the one detected from its title and description.
+pub fn article_language(article: &PotentialArticle, min_confidence: f64) -> Option<String> {
+    article
+        .language
+        .as_deref()
+        .and_then(normalize_language)
+        .or_else(|| {
+            let text = format!(
+                "{} {}",
+                article.title.clone().unwrap_or_default(),
+                to_plain_text(&article.desc)
+            );
+            detect_language(&text, min_confidence)
+        })
+}
+
+/// declared_language returns the language most articles declare
+pub fn declared_language(articles: &[PotentialArticle]) -> Option<String> {
+    let mut counts: Vec<(String, usize)> = vec![];
+    articles
+        .iter()
+        .filter_map(|a| a.language.as_deref().and_then(normalize_language))
+        .for_each(|language| match counts.iter_mut().find(|(l, _)| *l == language) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((language, 1)),
+        });
+    // max_by_key returns the last max element, rev() keeps the first one
+    counts
+        .into_iter()
+        .rev()
+        .max_by_key(|(_, count)| *count)
+        .map(|(language, _)| language)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn article(title: &str, desc: &str, language: Option<&str>) -> PotentialArticle {
+        PotentialArticle {
+            title: Some(title.to_string()),
+            desc: desc.to_string(),
+            language: language.map(|l| l.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_normalize_language() {
+        assert_eq!(normalize_language("en-US").as_deref(), Some("en"));
+        assert_eq!(normalize_language(" ja_JP ").as_deref(), Some("ja"));
+        assert_eq!(normalize_language("FR").as_deref(), Some("fr"));
+        assert_eq!(normalize_language(""), None);
+        assert_eq!(normalize_language("english"), None);
+    }
+
+    #[test]
+    fn test_article_language() {
+        assert_eq!(
+            article_language(&article("Bonjour", "", Some("fr-FR")), 0.5).as_deref(),
+            Some("fr")
+        );
+        assert_eq!(
+            article_language(
+                &article("東京で新しい美術館が開館", "<p>週末には多くの人が訪れました。</p>", None),
+                0.5
+            )
+            .as_deref(),
+            Some("ja")
+        );
+        assert_eq!(
+            article_language(
+                &article(
+                    "Le gouvernement présente son budget",
+                    "<p>Le projet de loi de finances sera débattu à l'Assemblée nationale dès la semaine prochaine.</p>",
+                    None
+                ),
+                0.5
+            )
+            .as_deref(),
+            Some("fr")
+        );
+        assert_eq!(article_language(&article("", "", None), 0.5), None);
+    }
+
+    #[test]
+    fn test_declared_language() {
+        assert_eq!(
+            declared_language(&[
+                article("", "", Some("en")),
+                article("", "", Some("ja")),
+                article("", "", Some("ja-JP")),
+                article("", "", None),
+            ])
+            .as_deref(),
+            Some("ja")
+        );
+        assert_eq!(declared_language(&[article("", "", None)]), None);
+    }
+}
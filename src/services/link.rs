@@ -0,0 +1,211 @@
+use chrono::Utc;
+use url::Url;
+use uuid::Uuid;
+
+use crate::{
+    db::model::FieldSort,
+    entities::potential_articles::PotentialArticle,
+    services::{
+        content::{attribute, tag_attributes},
+        http::HttpClient,
+    },
+};
+
+/// Query parameters only used for tracking, removed by `canonical_url`
+const TRACKING_PARAMS: &[&str] = &[
+    "fbclid", "gclid", "gclsrc", "dclid", "msclkid", "yclid", "igshid", "mc_cid", "mc_eid",
+    "_hsenc", "_hsmi", "mkt_tok", "oly_anon_id", "oly_enc_id", "vero_id", "wt_mc", "wt.mc_id",
+    "ncid", "__twitter_impression", "_ga", "_gl", "spm", "at_medium", "at_campaign",
+];
+/// Prefixes of tracking query parameters
+const TRACKING_PREFIXES: &[&str] = &["utm_", "pk_", "mtm_"];
+
+fn is_tracking_param(name: &str) -> bool {
+    let lower = name.to_lowercase();
+    TRACKING_PARAMS.contains(&lower.as_str())
+        || TRACKING_PREFIXES.iter().any(|p| lower.starts_with(p))
+}
+
+/// canonical_url turns `link` into a deduplication key:
+/// relative links are resolved against `base` (usually the feed's url),
+/// the host is lowercased, the fragment and tracking parameters are removed.
+/// Other query parameters are kept, in their original order.
+pub fn canonical_url(link: &str, base: Option<&str>) -> Result<String, url::ParseError> {
+    let link = link.trim();
+    let mut url = match Url::parse(link) {
+        Ok(url) => url,
+        Err(url::ParseError::RelativeUrlWithoutBase) => match base {
+            Some(b) => Url::parse(b)?.join(link)?,
+            None => return Err(url::ParseError::RelativeUrlWithoutBase),
+        },
+        Err(err) => return Err(err),
+    };
+    url.set_fragment(None);
+    let kept: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(k, _)| !is_tracking_param(k))
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect();
+    if kept.is_empty() {
+        url.set_query(None);
+    } else {
+        url.query_pairs_mut().clear().extend_pairs(kept);
+    }
+    if let Some(host) = url.host_str().map(|h| h.to_lowercase()) {
+        url.set_host(Some(&host))?;
+    }
+    Ok(url.to_string())
+}
+
+/// find_canonical_link looks for a `<link rel="canonical">` in an html page,
+/// resolving it against the page's `url`
+pub fn find_canonical_link(html: &str, url: &str) -> Option<String> {
+    tag_attributes(html, "link")
+        .iter()
+        .find(|attributes| {
+            attribute(attributes, "rel")
+                .map(|rel| rel.split_whitespace().any(|r| r.eq_ignore_ascii_case("canonical")))
+                .unwrap_or(false)
+        })
+        .and_then(|attributes| attribute(attributes, "href"))
+        .and_then(|href| canonical_url(href, Some(url)).ok())
+}
+
+/// fetch_canonical_link downloads the page at `url` and returns its canonical link, if declared
+pub async fn fetch_canonical_link(http: &HttpClient, url: &str, uuid: Uuid) -> Option<String> {
+    let html = http
+        .get_text(url, None, None)
+        .await
+        .map_err(|err| eprintln!("[{}] ({}) {}", uuid, Utc::now(), err))
+        .ok()?;
+    find_canonical_link(&html, url)
+}
+
+/// canonicalize_articles makes relative links absolute against `base`,
+/// sets the canonical link and link key of every article and drops articles sharing the same canonical link.
+pub fn canonicalize_articles(articles: &[PotentialArticle], base: &str) -> Vec<PotentialArticle> {
+    let canonicalized: Vec<PotentialArticle> = articles
+        .iter()
+        .map(|article| {
+            let mut article = article.clone();
+            if Url::parse(&article.link).is_err() {
+                if let Ok(absolute) = Url::parse(base).and_then(|b| b.join(&article.link)) {
+                    article.link = absolute.to_string();
+                }
+            }
+            article.canonical_link = canonical_url(&article.link, Some(base)).ok();
+            article.link_key = article.canonical_link.clone();
+            article
+        })
+        .collect();
+    dedupe_articles(&canonicalized)
+}
+
+/// remove_known_guids drops the articles which permalink guid is in `known_guids`,
+/// or already belongs to a previous article of `articles`.
+pub fn remove_known_guids(articles: &[PotentialArticle], known_guids: &[String]) -> Vec<PotentialArticle> {
+    let mut res: Vec<PotentialArticle> = vec![];
+    for article in articles {
+        let known = article.permalink_guid().map(|guid| {
+            known_guids.iter().any(|k| k == guid)
+                || res.iter().any(|a| a.permalink_guid() == Some(guid))
+        });
+        if known != Some(true) {
+            res.push(article.clone());
+        }
+    }
+    res
+}
+
+/// dedupe_articles keeps the first article of those sharing the same deduplication key
+pub fn dedupe_articles(articles: &[PotentialArticle]) -> Vec<PotentialArticle> {
+    let mut res: Vec<PotentialArticle> = vec![];
+    for article in articles {
+        if !res.iter().any(|a| a.sort_by_value() == article.sort_by_value()) {
+            res.push(article.clone());
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canonical_url() {
+        assert_eq!(
+            canonical_url(
+                "https://WWW.Example.com/a/b?utm_source=rss&id=3&fbclid=x&UTM_Medium=y#top",
+                None
+            )
+            .unwrap(),
+            "https://www.example.com/a/b?id=3"
+        );
+        assert_eq!(
+            canonical_url("https://example.com/a?utm_campaign=z", None).unwrap(),
+            "https://example.com/a"
+        );
+        assert_eq!(
+            canonical_url("/news/1.html?page=2", Some("https://www3.nhk.or.jp/news/easy/feed.xml")).unwrap(),
+            "https://www3.nhk.or.jp/news/1.html?page=2"
+        );
+        assert_eq!(
+            canonical_url("1.html", Some("https://www3.nhk.or.jp/news/easy/")).unwrap(),
+            "https://www3.nhk.or.jp/news/easy/1.html"
+        );
+        assert!(canonical_url("/relative", None).is_err());
+    }
+
+    #[test]
+    fn test_canonicalize_articles() {
+        let article = |link: &str| PotentialArticle {
+            link: link.to_string(),
+            ..Default::default()
+        };
+        let res = canonicalize_articles(
+            &[
+                article("https://example.com/a?utm_source=rss"),
+                article("/a?fbclid=1"),
+                article("/b"),
+            ],
+            "https://example.com/feed",
+        );
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0].link, "https://example.com/a?utm_source=rss");
+        assert_eq!(res[0].canonical_link.as_deref(), Some("https://example.com/a"));
+        assert_eq!(res[0].link_key, res[0].canonical_link);
+        assert_eq!(res[1].link, "https://example.com/b");
+    }
+
+    #[test]
+    fn test_remove_known_guids() {
+        let article = |link: &str, guid: &str, is_permalink: bool| PotentialArticle {
+            link: link.to_string(),
+            guid: Some(guid.to_string()),
+            guid_is_permalink: Some(is_permalink),
+            ..Default::default()
+        };
+        let res = remove_known_guids(
+            &[
+                article("https://example.com/a", "https://example.com/?p=1", true),
+                article("https://example.com/b", "https://example.com/?p=2", true),
+                article("https://example.com/c", "https://example.com/?p=2", true),
+                article("https://example.com/d", "https://example.com/?p=1", false),
+            ],
+            &["https://example.com/?p=1".to_string()],
+        );
+        let links: Vec<&str> = res.iter().map(|a| a.link.as_str()).collect();
+        assert_eq!(links, vec!["https://example.com/b", "https://example.com/d"]);
+    }
+
+    #[test]
+    fn test_find_canonical_link() {
+        let html = r#"<html><head><link rel="stylesheet" href="/s.css"><link rel="canonical" href="/article/1?utm_source=x"></head></html>"#;
+        assert_eq!(
+            find_canonical_link(html, "https://example.com/amp/article/1"),
+            Some("https://example.com/article/1".to_string())
+        );
+        assert_eq!(find_canonical_link("<p>nothing</p>", "https://example.com"), None);
+    }
+}
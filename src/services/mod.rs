@@ -1,5 +1,21 @@
 pub mod channel;
+pub mod charset;
+pub mod content;
+pub mod credentials;
+pub mod discovery;
+pub mod duplicate;
+pub mod filter;
+pub mod http;
+pub mod japanese;
+pub mod language;
+pub mod link;
 pub mod bakery;
+pub mod bakery_queue;
 pub mod panya;
+pub mod redirect;
 pub mod vec;
-pub mod rss;
\ No newline at end of file
+pub mod rss;
+pub mod schedule;
+pub mod search;
+pub mod taxonomy;
+pub mod websub;
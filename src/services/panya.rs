@@ -1,39 +1,174 @@
+use chrono::Utc;
+use futures::{stream, StreamExt};
+use mongodb::bson::doc;
+use uuid::Uuid;
+
 use crate::{
     db::{
-        channel::{get_channel_id, Channels},
+        channel::get_channel_id,
         items::Items,
-        model::CollectionModel,
+        model::{CollectionModel, FieldSort, SortOrder},
+    },
+    config::Settings,
+    entities::{
+        channel::Channel, potential_articles::PotentialArticle, refresh_report::RefreshReport,
     },
-    entities::{channel::Channel, potential_articles::PotentialArticle, source_type::SourceType},
     error::Error,
-    services::vec::RemoveReplaceExisting,
+    services::{
+        content::process_content,
+        duplicate::assign_clusters,
+        filter::{apply_filters, compile_rules},
+        japanese::normalize_article,
+        language::article_language,
+        http::HttpClient,
+        taxonomy::Taxonomy,
+        link::{canonicalize_articles, dedupe_articles, fetch_canonical_link, remove_known_guids},
+        vec::RemoveReplaceExisting,
+    },
+    utils::{to_timestamp_ms, DBBag},
 };
-/// process_data_and_fetch_items compares fetched articles from bakery against existing ones in DB,
-/// then insert those not existing and then returns the latest `limit` number of articles.
-pub async fn process_data(
-    articles: &Vec<PotentialArticle>,
+
+/// Pages fetched at once when following the canonical links of new articles
+const CANONICAL_LINK_FETCHES: usize = 4;
+
+/// link_near_duplicates clusters `articles` with the near-duplicates stored recently for the same `tenant`,
+/// at most the `max_candidates` latest ones, or found in `articles` themselves.
+async fn link_near_duplicates(
     items_coll: &Items<PotentialArticle>,
-    channels_coll: &Channels<Channel>,
-    channel_name: &str,
-    channel_url: &str,
-    source_type: SourceType,
+    tenant: &str,
+    articles: &mut [PotentialArticle],
+    settings: &Settings,
 ) -> Result<(), Error> {
-    // find existing links
-    let existing_links = items_coll.find_by_field_values(&articles, "link", 0).await;
-    // picks out existing links in db
-    let mut to_insert = articles.remove_existing(&existing_links);
+    let mut known = items_coll
+        .find_latests(
+            "create_date",
+            Utc::now().timestamp_millis() - settings.duplicates.window_hours * 3_600_000,
+            settings.duplicates.max_candidates,
+            SortOrder::DESC,
+            doc! {"tenant": tenant, "fingerprint": {"$ne": null}},
+        )
+        .await
+        .unwrap_or_default();
+    // the first stored near-duplicate is the canonical item of a cluster
+    known.reverse();
+    let new_canonicals = assign_clusters(articles, &known, settings.duplicates.max_distance);
+    for canonical in new_canonicals {
+        items_coll
+            .set_cluster_id(tenant, &canonical.sort_by_value(), &canonical.cluster_key())
+            .await?;
+    }
+    Ok(())
+}
+
+/// process_data compares fetched articles from bakery against existing ones in DB,
+/// drops those filtered by the channel's rules, then inserts the remaining ones.
+/// Returns a report of the refresh.
+pub async fn process_data(
+    articles: &[PotentialArticle],
+    db_bag: &DBBag,
+    channel: &Channel,
+    taxonomy: &Taxonomy,
+    http: &HttpClient,
+    settings: &Settings,
+    log_id: Uuid,
+) -> Result<RefreshReport, Error> {
+    let mut report = RefreshReport {
+        date: Utc::now().timestamp_millis(),
+        fetched: articles.len() as i64,
+        ..Default::default()
+    };
+    let items_coll = &db_bag.items_coll;
+    let tenant = &channel.tenant;
+    let channel_name = &channel.name;
+    let articles = canonicalize_articles(articles, &channel.url);
+    // picks out links already in db, whether as the page's canonical link or the feed's link key
+    let keys: Vec<String> = articles.iter().map(|pa| pa.sort_by_value()).collect();
+    let known_links = items_coll.find_known_links(tenant, &keys).await;
+    let mut to_insert: Vec<PotentialArticle> = articles
+        .into_iter()
+        .filter(|pa| !known_links.contains(&pa.sort_by_value()))
+        .collect();
+    // permalink guids are an alternate key, e.g. for items which link changed
+    let guids: Vec<&str> = to_insert.iter().filter_map(|pa| pa.permalink_guid()).collect();
+    if !guids.is_empty() {
+        let known_guids = items_coll.find_permalink_guids(tenant, &guids).await;
+        to_insert = remove_known_guids(&to_insert, &known_guids);
+    }
+    // items are not inserted unfiltered when the channel's rules are broken
+    if !channel.get_filters().is_empty() && !to_insert.is_empty() {
+        match compile_rules(channel.get_filters()) {
+            Ok(rules) => {
+                let (kept, filtered) = apply_filters(to_insert, &rules);
+                to_insert = kept;
+                report.filtered = filtered as i64;
+            }
+            Err(err) => {
+                eprintln!("[{}] ({}) {}", log_id, Utc::now(), err);
+                report.warnings.push(format!("filter rules could not be compiled: {}", err));
+                return Ok(report);
+            }
+        }
+    }
+    if settings.follow_canonical_links && !to_insert.is_empty() {
+        let fetches: Vec<_> = to_insert
+            .iter()
+            .map(|pa| fetch_canonical_link(http, &pa.link, log_id))
+            .collect();
+        let canonicals: Vec<Option<String>> = stream::iter(fetches)
+            .buffered(CANONICAL_LINK_FETCHES)
+            .collect()
+            .await;
+        for (pa, canonical) in to_insert.iter_mut().zip(canonicals) {
+            if let Some(canonical) = canonical {
+                pa.canonical_link = Some(canonical);
+            }
+        }
+        let existing_canonicals = items_coll
+            .find_by_field_values(&to_insert, "canonical_link", 0, doc! {"tenant": tenant})
+            .await;
+        to_insert = dedupe_articles(&to_insert).remove_existing(&existing_canonicals);
+    }
     // something to insert
     if !to_insert.is_empty() {
-        let channel_id =
-            get_channel_id(&channels_coll, channel_name, channel_url, source_type).await?;
+        // channels read from the db are already known, whatever their name
+        let channel_id = match channel.id {
+            0 => {
+                get_channel_id(
+                    &db_bag.channels_coll,
+                    tenant,
+                    channel_name,
+                    &channel.url,
+                    channel.source_type.clone(),
+                )
+                .await?
+            }
+            id => id,
+        };
         to_insert.iter_mut().for_each(|pa| {
+            pa.create_date = to_timestamp_ms(pa.create_date);
             pa.channel_name = Some(channel_name.to_string());
             pa.channel_id = Some(channel_id);
+            pa.tenant = Some(tenant.to_string());
+            if let Some(normalization) = &channel.text_normalization {
+                normalize_article(pa, normalization);
+            }
+            let tags = taxonomy.tags_of(
+                pa.categories.as_deref().unwrap_or_default(),
+                channel.get_default_tags(),
+            );
+            pa.tags = Some(tags).filter(|t| !t.is_empty());
+            pa.language = article_language(pa, settings.language_min_confidence)
+                .or_else(|| channel.language.clone());
+            process_content(pa, settings.excerpt_length);
         });
+        if settings.duplicates.enabled {
+            link_near_duplicates(items_coll, tenant, &mut to_insert, settings).await?;
+        }
         return items_coll.insert_many(&to_insert, None).await.map(|res| {
             println!("items_coll.insert_many{:?}", res);
-            return ();
+            report.inserted = res.inserted_ids.len() as i64;
+            report
         });
     }
-    Ok(())
+    Ok(report)
 }
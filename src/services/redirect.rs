@@ -0,0 +1,108 @@
+use url::Url;
+
+use crate::entities::redirect::PendingRedirect;
+
+/// RedirectOutcome is what becomes of a channel's url after a refresh
+#[derive(Debug, Clone, PartialEq)]
+pub enum RedirectOutcome {
+    // the pending redirect to record, none when the url was not redirected
+    Pending(Option<PendingRedirect>),
+    // the url moved for good to this target
+    Move(String),
+    // a confirmed move which is not followed, and stays pending
+    Held(PendingRedirect),
+}
+
+/// may_move tells whether a channel can move from `url` to `target`.
+/// Channels with headers or a credential (`has_secrets`) only move within their host,
+/// and never from https to http, since those are sent to the channel's url.
+pub fn may_move(url: &str, target: &str, has_secrets: bool) -> bool {
+    if !has_secrets {
+        return true;
+    }
+    match (Url::parse(url), Url::parse(target)) {
+        (Ok(url), Ok(target)) => {
+            url.host_str() == target.host_str() && (url.scheme() != "https" || target.scheme() == "https")
+        }
+        _ => false,
+    }
+}
+
+/// observe_redirect counts consecutive refreshes permanently redirected to the same target.
+/// The channel moves once `confirmations` of them were seen in a row, if it `may_move` there;
+/// any other answer starts over.
+pub fn observe_redirect(
+    pending: Option<&PendingRedirect>,
+    moved_to: Option<&str>,
+    now: i64,
+    confirmations: u32,
+    movable: bool,
+) -> RedirectOutcome {
+    let Some(target) = moved_to else {
+        return RedirectOutcome::Pending(None);
+    };
+    let observed = match pending {
+        Some(p) if p.target == target => PendingRedirect {
+            count: p.count + 1,
+            ..p.clone()
+        },
+        _ => PendingRedirect {
+            target: target.to_string(),
+            count: 1,
+            first_seen: now,
+        },
+    };
+    match observed.count >= confirmations {
+        true if movable => RedirectOutcome::Move(observed.target),
+        true => RedirectOutcome::Held(observed),
+        false => RedirectOutcome::Pending(Some(observed)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_observe_redirect() {
+        let target = "https://new.example.com/feed";
+        let first = match observe_redirect(None, Some(target), 10, 3, true) {
+            RedirectOutcome::Pending(Some(p)) => p,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!((first.count, first.first_seen), (1, 10));
+        let second = match observe_redirect(Some(&first), Some(target), 20, 3, true) {
+            RedirectOutcome::Pending(Some(p)) => p,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!((second.count, second.first_seen), (2, 10));
+        assert_eq!(
+            observe_redirect(Some(&second), Some(target), 30, 3, true),
+            RedirectOutcome::Move(target.to_string())
+        );
+        // another target, or no redirect, starts over
+        let other = observe_redirect(Some(&second), Some("https://other.example.com"), 30, 3, true);
+        assert!(matches!(other, RedirectOutcome::Pending(Some(PendingRedirect { count: 1, .. }))));
+        assert_eq!(observe_redirect(Some(&second), None, 30, 3, true), RedirectOutcome::Pending(None));
+        assert_eq!(
+            observe_redirect(None, Some(target), 10, 1, true),
+            RedirectOutcome::Move(target.to_string())
+        );
+        assert!(matches!(
+            observe_redirect(Some(&second), Some(target), 30, 3, false),
+            RedirectOutcome::Held(PendingRedirect { count: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn test_may_move() {
+        let url = "https://example.com/feed";
+        assert!(may_move(url, "https://example.com/rss", true));
+        assert!(may_move(url, "https://new.example.com/feed", false));
+        assert!(may_move(url, "http://example.com/feed", false));
+        // headers and credentials are neither sent to another host, nor over http
+        assert!(!may_move(url, "https://new.example.com/feed", true));
+        assert!(!may_move(url, "http://example.com/feed", true));
+        assert!(may_move("http://example.com/feed", "https://example.com/feed", true));
+    }
+}
@@ -1,51 +1,129 @@
-use crate::entities::{potential_articles::PotentialArticle, rss::Rss};
+use crate::{
+    entities::{
+        channel::Channel, credential::CredentialAuth, detected_encoding::DetectedEncoding,
+        potential_articles::PotentialArticle,
+        refresh_hints::RefreshHints, rss::Rss,
+    },
+    error::Error,
+    services::{
+        http::{HttpClient, HttpError, Page},
+        websub::{hub_links, HubLinks},
+    },
+};
 use chrono::Utc;
 use serde_xml_rs::from_str;
 use uuid::Uuid;
 
-pub async fn get_cookies_from_rss(
-    channel_url: &str,
-    channel_id: i32,
-    uuid: Uuid,
-) -> Option<Vec<PotentialArticle>> {
-    let url = channel_url;
-    let response = reqwest::get(url)
-        .await
-        .map_err(|err| eprintln!("[{}] ({}) {}", uuid, Utc::now(), err))
-        .ok()?;
-    let raw_data = response
-        .text()
-        .await
-        .map_err(|err| eprintln!("[{}] ({}) {}", uuid, Utc::now(), err))
-        .ok()?;
-    let rss: Rss = from_str(&raw_data)
-        .map_err(|err| eprintln!("[{}] ({}) {}", uuid, Utc::now(), err))
-        .ok()?;
+/// RssFetch holds the articles of a feed, the url it permanently moved to, if any,
+/// the publisher's refresh hints, the WebSub hub the feed advertises and the encoding it was read with
+#[derive(Debug, Default)]
+pub struct RssFetch {
+    pub articles: Vec<PotentialArticle>,
+    // why the fetched body could not be read as a feed
+    pub parse_error: Option<String>,
+    pub moved_to: Option<String>,
+    pub hints: RefreshHints,
+    pub hub: HubLinks,
+    pub encoding: Option<DetectedEncoding>,
+}
+
+/// parse_rss reads the articles of a channel's feed, along with the feed's refresh hints
+pub fn parse_rss(body: &str, channel: &Channel) -> Result<(Vec<PotentialArticle>, RefreshHints), Error> {
+    let rss: Rss = from_str(body).map_err(|err| Error(err.to_string()))?;
+    let url = &channel.url;
+    let image_sources = channel.get_image_sources();
     let mut res: Vec<PotentialArticle> = vec![];
     rss.channel.item.iter().for_each(|item| {
         res.push(PotentialArticle {
             link: item.link.clone().unwrap_or_default(),
-            img: item.get_img(),
+            canonical_link: None,
+            link_key: None,
+            img: item.get_img(image_sources),
             title: item.get_title(),
             categories: item.get_categories(),
+            tags: None,
+            language: item.language.clone().or(rss.channel.language.clone()),
+            reading: None,
+            ruby: None,
             desc: item.get_desc(),
+            desc_html: None,
+            excerpt: None,
             create_date: item.get_create_date(Utc::now()),
             channel_name: Some(rss.channel.get_channel_name(url)),
-            channel_id: Some(channel_id),
+            channel_id: Some(channel.id),
+            tenant: None,
+            fingerprint: None,
+            cluster_id: None,
+            content: item.get_content(),
+            authors: item.get_authors(),
+            guid: item.get_guid().map(|(guid, _)| guid),
+            guid_is_permalink: item.get_guid().map(|(_, is_permalink)| is_permalink),
+            enclosures: item.get_enclosures(),
+            comments: item.comments.clone().filter(|c| !c.trim().is_empty()),
+            update_date: item.get_update_date(),
+            podcast: item.get_podcast(),
         })
     });
-    Some(res)
+    Ok((res, rss.channel.get_refresh_hints()))
+}
+
+/// get_cookies_from_rss fetches and parses the feed of a channel
+pub async fn get_cookies_from_rss(
+    http: &HttpClient,
+    channel: &Channel,
+    auth: Option<&CredentialAuth>,
+    uuid: Uuid,
+) -> Result<RssFetch, HttpError> {
+    let page = http
+        .get_page(&channel.url, channel.headers.as_ref(), auth)
+        .await
+        .map_err(|err| {
+            eprintln!("[{}] ({}) {:?}", uuid, Utc::now(), err);
+            err
+        })?;
+    Ok(read_feed(page, channel, uuid))
+}
+
+/// read_feed parses the fetched feed of a channel.
+/// A feed which cannot be parsed gives no article, and its redirect is not a move.
+fn read_feed(page: Page, channel: &Channel, uuid: Uuid) -> RssFetch {
+    let (parse_error, (articles, feed_hints)) = match parse_rss(&page.body, channel) {
+        Ok(feed) => (None, feed),
+        Err(err) => {
+            eprintln!("[{}] ({}) {}", uuid, Utc::now(), err);
+            (Some(format!("{} is not a valid feed: {}", channel.url, err)), Default::default())
+        }
+    };
+    RssFetch {
+        articles,
+        moved_to: page.moved_to.filter(|_| parse_error.is_none()),
+        parse_error,
+        hints: RefreshHints {
+            max_age: page.max_age,
+            retry_after: page.retry_after,
+            ..feed_hints
+        },
+        hub: hub_links(&page.body),
+        encoding: Some(page.encoding),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        entities::{
+            image_source::{ImageSource, DEFAULT_IMAGE_SOURCES}, potential_articles::{ArticleEnclosure, Podcast},
+            source_type::SourceType,
+        },
+        services::charset::transcode,
+    };
 
     #[test]
     fn test_i_can_deserialize_rss() {
         let rss: Rss = from_str(TEST_1).unwrap();
         assert_eq!(
-            rss.channel.item.first().unwrap().content.as_ref().unwrap().url,
+            rss.channel.item.first().unwrap().content.first().unwrap().url,
             "https://img.lemde.fr/2024/07/17/441/0/4000/2000/644/322/60/0/8e5c70e_1721206405128-000-1wb54n.jpg"
         );
     }
@@ -63,6 +141,258 @@ mod tests {
             rss.channel.item.first().unwrap().category.clone().unwrap(),
         );
     }
+    #[test]
+    fn test_i_can_resolve_images() {
+        let rss: Rss = from_str(TEST_3).unwrap();
+        let items = &rss.channel.item;
+        // largest media:content
+        assert_eq!(items[0].get_img(DEFAULT_IMAGE_SOURCES), "https://example.com/large.jpg");
+        // media:group before media:thumbnail
+        assert_eq!(items[1].get_img(DEFAULT_IMAGE_SOURCES), "https://example.com/group.jpg");
+        assert_eq!(
+            items[1].get_img(&[ImageSource::MediaThumbnail, ImageSource::MediaGroup]),
+            "https://example.com/thumb.jpg"
+        );
+        // audio enclosures are skipped
+        assert_eq!(items[2].get_img(DEFAULT_IMAGE_SOURCES), "https://example.com/cover.png");
+        assert_eq!(items[3].get_img(DEFAULT_IMAGE_SOURCES), "https://example.com/episode.jpg");
+        // relative <img> of content:encoded, resolved against the item's link
+        assert_eq!(items[4].get_img(DEFAULT_IMAGE_SOURCES), "https://example.com/img/encoded.jpg");
+        assert_eq!(
+            items[4].get_img(&[ImageSource::Description]),
+            "https://example.com/desc.jpg"
+        );
+        assert_eq!(items[4].get_img(&[ImageSource::MediaContent]), "");
+        assert_eq!(
+            from_str::<Rss>(TEST_1).unwrap().channel.item[0].get_img(DEFAULT_IMAGE_SOURCES),
+            "https://img.lemde.fr/2024/07/17/441/0/4000/2000/644/322/60/0/8e5c70e_1721206405128-000-1wb54n.jpg"
+        );
+    }
+
+    #[test]
+    fn test_i_can_read_item_metadata() {
+        let rss: Rss = from_str(TEST_4).unwrap();
+        let items = &rss.channel.item;
+        assert_eq!(
+            items[0].get_authors(),
+            Some(vec!["Jane Doe".to_string(), "John Roe".to_string()])
+        );
+        assert_eq!(
+            items[0].get_guid(),
+            Some(("https://example.com/?p=42".to_string(), true))
+        );
+        assert_eq!(items[0].get_content().as_deref(), Some("<p>Full <b>content</b></p>"));
+        assert_eq!(
+            items[0].get_enclosures(),
+            Some(vec![ArticleEnclosure {
+                url: "https://example.com/episode.mp3".to_string(),
+                kind: Some("audio/mpeg".to_string()),
+                length: Some(1234),
+            }])
+        );
+        assert_eq!(items[0].comments.as_deref(), Some("https://example.com/a#comments"));
+        assert_eq!(items[0].get_update_date(), Some(1721210400000));
+        assert_eq!(items[1].get_guid(), Some(("tag:example.com,2024:2".to_string(), false)));
+        assert_eq!(items[1].get_authors(), None);
+        assert_eq!(items[1].get_enclosures(), None);
+        assert_eq!(items[1].get_update_date(), None);
+    }
+
+    #[test]
+    fn test_i_can_read_podcast_episodes() {
+        let rss: Rss = from_str(TEST_5).unwrap();
+        let items = &rss.channel.item;
+        assert_eq!(
+            items[0].get_podcast(),
+            Some(Podcast {
+                duration: Some(3723),
+                episode: Some(12),
+                season: Some(2),
+                explicit: Some(false),
+                image: Some("https://example.com/ep12.jpg".to_string()),
+                audio: Some(ArticleEnclosure {
+                    url: "https://example.com/ep12.mp3".to_string(),
+                    kind: Some("audio/mpeg".to_string()),
+                    length: Some(5650889),
+                }),
+            })
+        );
+        assert_eq!(items[0].get_authors(), Some(vec!["Patishie FM".to_string()]));
+        let second = items[1].get_podcast().unwrap();
+        assert_eq!(second.duration, Some(754));
+        assert_eq!(second.explicit, Some(true));
+        assert_eq!(items[2].get_podcast().unwrap().duration, Some(1800));
+        assert_eq!(from_str::<Rss>(TEST_2).unwrap().channel.item[0].get_podcast(), None);
+    }
+
+    #[test]
+    fn test_i_can_ignore_overflowing_durations() {
+        let duration = |duration: &str| {
+            let rss: Rss = from_str(&format!(
+                r#"<rss version="2.0" xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd"><channel>
+                <item><title>a</title><link>https://example.com/a</link>
+                <enclosure url="https://example.com/a.mp3" type="audio/mpeg" length="1" />
+                <itunes:duration>{}</itunes:duration></item></channel></rss>"#,
+                duration
+            ))
+            .unwrap();
+            rss.channel.item[0].get_podcast().and_then(|podcast| podcast.duration)
+        };
+        assert_eq!(duration("99999999999999999999"), None);
+        assert_eq!(duration("999999999999999999:00"), None);
+        assert_eq!(duration("inf"), None);
+        assert_eq!(duration("01:02:03"), Some(3723));
+    }
+
+    #[test]
+    fn test_i_can_only_follow_redirects_of_feeds() {
+        let channel = Channel::new("default", "news", "https://example.com/feed", SourceType::RSSFeed);
+        let page = |body: &str| {
+            let (body, encoding) = transcode(body.as_bytes(), None);
+            Page {
+                body,
+                encoding,
+                moved_to: Some("https://new.example.com/feed".to_string()),
+                max_age: None,
+                retry_after: None,
+            }
+        };
+        let fetch = read_feed(page(TEST_1), &channel, Uuid::new_v4());
+        assert_eq!(fetch.moved_to.as_deref(), Some("https://new.example.com/feed"));
+        // e.g. a login page
+        let fetch = read_feed(page("<!doctype html><html></html>"), &channel, Uuid::new_v4());
+        assert_eq!((fetch.moved_to, fetch.articles.len()), (None, 0));
+        assert!(fetch.parse_error.is_some());
+    }
+
+    #[test]
+    fn test_i_can_read_refresh_hints() {
+        let rss: Rss = from_str(
+            r#"<rss version="2.0"><channel><title>Hints</title><ttl> 30 </ttl>
+            <skipHours><hour>0</hour><hour>1</hour><hour>25</hour></skipHours>
+            <skipDays><day>Sunday</day></skipDays>
+            <item><title>a</title><link>https://example.com/a</link></item>
+            </channel></rss>"#,
+        )
+        .unwrap();
+        assert_eq!(
+            rss.channel.get_refresh_hints(),
+            RefreshHints {
+                ttl: Some(1_800_000),
+                skip_hours: vec![0, 1],
+                skip_days: vec!["Sunday".to_string()],
+                ..Default::default()
+            }
+        );
+        // sy:updatePeriod hourly, sy:updateFrequency 1
+        let hints = from_str::<Rss>(TEST_2).unwrap().channel.get_refresh_hints();
+        assert_eq!(hints.syndication, Some(3_600_000));
+        assert_eq!(hints.ttl, None);
+        let rss: Rss = from_str(
+            r#"<rss version="2.0"><channel><title>Hints</title><ttl>9223372036854775807</ttl>
+            <item><title>a</title><link>https://example.com/a</link></item>
+            </channel></rss>"#,
+        )
+        .unwrap();
+        assert_eq!(rss.channel.get_refresh_hints().ttl, Some(i64::MAX));
+    }
+
+    #[test]
+    fn test_i_can_read_shift_jis_feeds() {
+        let feed = r#"<?xml version="1.0" encoding="Shift_JIS"?><rss version="2.0"><channel><title>ニュース</title>
+            <item><title>東京の天気</title><link>https://example.jp/a</link></item></channel></rss>"#;
+        let (body, _, _) = encoding_rs::SHIFT_JIS.encode(feed);
+        let (text, encoding) = transcode(&body, Some("application/rss+xml"));
+        assert_eq!(encoding.name, "Shift_JIS");
+        let rss: Rss = from_str(&text).unwrap();
+        assert_eq!(rss.channel.title.as_deref(), Some("ニュース"));
+        assert_eq!(rss.channel.item.first().unwrap().title, "東京の天気");
+    }
+
+    const TEST_5: &str = r#"
+    <rss version="2.0" xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd">
+        <channel>
+            <title>Patishie FM</title>
+            <item>
+                <title>Episode 12</title>
+                <itunes:author>Patishie FM</itunes:author>
+                <itunes:duration>01:02:03</itunes:duration>
+                <itunes:episode>12</itunes:episode>
+                <itunes:season>2</itunes:season>
+                <itunes:explicit>no</itunes:explicit>
+                <itunes:image href="https://example.com/ep12.jpg"/>
+                <enclosure url="https://example.com/ep12.mp3" type="audio/mpeg" length="5650889"/>
+            </item>
+            <item>
+                <title>Episode 11</title>
+                <itunes:duration>12:34</itunes:duration>
+                <itunes:explicit>true</itunes:explicit>
+            </item>
+            <item>
+                <title>Episode 10</title>
+                <itunes:duration>1800</itunes:duration>
+            </item>
+        </channel>
+    </rss>"#;
+
+    const TEST_4: &str = r#"
+    <rss version="2.0" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:atom="http://www.w3.org/2005/Atom" xmlns:content="http://purl.org/rss/1.0/modules/content/">
+        <channel>
+            <title>Metadata</title>
+            <item>
+                <title>a</title>
+                <link>https://example.com/a</link>
+                <dc:creator>Jane Doe</dc:creator>
+                <dc:creator>John Roe</dc:creator>
+                <author>Jane Doe</author>
+                <guid>https://example.com/?p=42</guid>
+                <content:encoded><![CDATA[<p>Full <b>content</b></p>]]></content:encoded>
+                <enclosure url="https://example.com/episode.mp3" type="audio/mpeg" length="1234"/>
+                <comments>https://example.com/a#comments</comments>
+                <atom:updated>2024-07-17T10:00:00Z</atom:updated>
+            </item>
+            <item>
+                <title>b</title>
+                <guid isPermaLink="false">tag:example.com,2024:2</guid>
+            </item>
+        </channel>
+    </rss>"#;
+
+    const TEST_3: &str = r#"
+    <rss version="2.0" xmlns:media="http://search.yahoo.com/mrss/" xmlns:content="http://purl.org/rss/1.0/modules/content/" xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd">
+        <channel>
+            <title>Images</title>
+            <item>
+                <title>media contents</title>
+                <media:content url="https://example.com/small.jpg" width="100" height="50"/>
+                <media:content url="https://example.com/large.jpg" width="1200" height="600"/>
+                <media:content url="https://example.com/video.mp4" medium="video" width="1920" height="1080"/>
+            </item>
+            <item>
+                <title>group and thumbnail</title>
+                <media:group>
+                    <media:content url="https://example.com/group.jpg" type="image/jpeg"/>
+                </media:group>
+                <media:thumbnail url="https://example.com/thumb.jpg"/>
+            </item>
+            <item>
+                <title>enclosures</title>
+                <enclosure url="https://example.com/episode.mp3" type="audio/mpeg" length="1234"/>
+                <enclosure url="https://example.com/cover.png" type="image/png" length="42"/>
+            </item>
+            <item>
+                <title>itunes</title>
+                <itunes:image href="https://example.com/episode.jpg"/>
+            </item>
+            <item>
+                <title>html</title>
+                <link>https://example.com/articles/1</link>
+                <description><![CDATA[<p><img src="https://example.com/desc.jpg"/>text</p>]]></description>
+                <content:encoded><![CDATA[<p>text <img alt="a" src="/img/encoded.jpg"></p>]]></content:encoded>
+            </item>
+        </channel>
+    </rss>"#;
+
     const TEST_1: &str = r#"
     <rss xmlns:atom="http://www.w3.org/2005/Atom" xmlns:media="http://search.yahoo.com/mrss/" xmlns:content="http://purl.org/rss/1.0/modules/content/" version="2.0">
         <channel>
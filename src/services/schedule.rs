@@ -0,0 +1,144 @@
+use chrono::{DateTime, Datelike, Duration, DurationRound, Timelike, Utc};
+
+use crate::entities::refresh_hints::RefreshHints;
+
+const HOUR_MS: i64 = 3_600_000;
+
+/// syndication_interval reads <sy:updatePeriod> and <sy:updateFrequency>, which default
+/// to "daily" and 1 when only one of them is given
+pub fn syndication_interval(period: Option<&str>, frequency: Option<i32>) -> Option<i64> {
+    if period.is_none() && frequency.is_none() {
+        return None;
+    }
+    let period_ms = match period.map(|p| p.trim().to_ascii_lowercase()).as_deref() {
+        Some("hourly") => HOUR_MS,
+        None | Some("daily") => 24 * HOUR_MS,
+        Some("weekly") => 7 * 24 * HOUR_MS,
+        Some("monthly") => 30 * 24 * HOUR_MS,
+        Some("yearly") => 365 * 24 * HOUR_MS,
+        Some(_) => return None,
+    };
+    Some(period_ms / frequency.unwrap_or(1).max(1) as i64)
+}
+
+/// hinted_interval is the minimum interval between two refreshes the publisher asks for, 0 if none
+pub fn hinted_interval(hints: &RefreshHints) -> i64 {
+    [hints.ttl, hints.syndication, hints.max_age, hints.retry_after]
+        .into_iter()
+        .flatten()
+        .max()
+        .unwrap_or(0)
+        .max(0)
+}
+
+/// is_skipped tells whether `timestamp` (ms) falls in the skipped hours or days of a feed
+pub fn is_skipped(timestamp: i64, hints: &RefreshHints) -> bool {
+    let Some(date) = DateTime::<Utc>::from_timestamp_millis(timestamp) else {
+        return false;
+    };
+    let weekday = date.weekday().to_string();
+    hints.skip_hours.contains(&date.hour())
+        || hints.skip_days.iter().any(|day| {
+            // chrono names days "Mon", "Tue"...
+            day.trim().to_ascii_lowercase().starts_with(&weekday.to_ascii_lowercase())
+        })
+}
+
+/// next_allowed returns the first time from `timestamp` which is not skipped.
+/// Feeds skipping every hour are not held back.
+pub fn next_allowed(timestamp: i64, hints: &RefreshHints) -> i64 {
+    let mut next = timestamp;
+    for _ in 0..(7 * 24) {
+        if !is_skipped(next, hints) {
+            return next;
+        }
+        next = DateTime::<Utc>::from_timestamp_millis(next)
+            .and_then(|date| date.duration_trunc(Duration::hours(1)).ok())
+            .map(|hour| hour.timestamp_millis() + HOUR_MS)
+            .unwrap_or(next + HOUR_MS);
+    }
+    timestamp
+}
+
+/// refresh_frequency returns the delay (ms) before the next refresh of a channel which configured
+/// frequency is `base`: never shorter than what the publisher asks, capped at `max_hinted`,
+/// or than the channel's `min_interval` override, then pushed out of skipped hours and days.
+pub fn refresh_frequency(
+    base: i64,
+    hints: &RefreshHints,
+    min_interval: Option<i64>,
+    max_hinted: i64,
+    now: i64,
+) -> i64 {
+    let interval = base.max(min_interval.unwrap_or_else(|| hinted_interval(hints).min(max_hinted)));
+    next_allowed(now.saturating_add(interval), hints) - now
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_syndication_interval() {
+        assert_eq!(syndication_interval(Some("hourly"), Some(2)), Some(HOUR_MS / 2));
+        assert_eq!(syndication_interval(Some(" Daily "), None), Some(24 * HOUR_MS));
+        assert_eq!(syndication_interval(None, Some(4)), Some(6 * HOUR_MS));
+        assert_eq!(syndication_interval(Some("weekly"), Some(0)), Some(7 * 24 * HOUR_MS));
+        assert_eq!(syndication_interval(Some("fortnightly"), None), None);
+        assert_eq!(syndication_interval(None, None), None);
+    }
+
+    #[test]
+    fn test_hinted_interval() {
+        let hints = RefreshHints {
+            ttl: Some(60_000),
+            max_age: Some(300_000),
+            ..Default::default()
+        };
+        assert_eq!(hinted_interval(&hints), 300_000);
+        assert_eq!(hinted_interval(&RefreshHints::default()), 0);
+    }
+
+    #[test]
+    fn test_next_allowed() {
+        // 2024-10-14 is a Monday, 10:30 GMT
+        let monday = DateTime::parse_from_rfc3339("2024-10-14T10:30:00Z").unwrap().timestamp_millis();
+        let hints = RefreshHints {
+            skip_hours: vec![10, 11],
+            ..Default::default()
+        };
+        assert!(is_skipped(monday, &hints));
+        assert_eq!(next_allowed(monday, &hints), monday + 90 * 60_000);
+        let hints = RefreshHints {
+            skip_days: vec!["Monday".to_string()],
+            ..Default::default()
+        };
+        let tuesday = DateTime::parse_from_rfc3339("2024-10-15T00:00:00Z").unwrap().timestamp_millis();
+        assert_eq!(next_allowed(monday, &hints), tuesday);
+        let hints = RefreshHints {
+            skip_hours: (0..24).collect(),
+            ..Default::default()
+        };
+        assert_eq!(next_allowed(monday, &hints), monday);
+    }
+
+    #[test]
+    fn test_refresh_frequency() {
+        let now = DateTime::parse_from_rfc3339("2024-10-14T10:30:00Z").unwrap().timestamp_millis();
+        let hints = RefreshHints {
+            ttl: Some(2 * HOUR_MS),
+            ..Default::default()
+        };
+        assert_eq!(refresh_frequency(60_000, &hints, None, 24 * HOUR_MS, now), 2 * HOUR_MS);
+        assert_eq!(refresh_frequency(60_000, &hints, None, HOUR_MS, now), HOUR_MS);
+        assert_eq!(refresh_frequency(3 * HOUR_MS, &hints, None, 24 * HOUR_MS, now), 3 * HOUR_MS);
+        // the override replaces the publisher's hints
+        assert_eq!(refresh_frequency(60_000, &hints, Some(0), 24 * HOUR_MS, now), 60_000);
+        assert_eq!(refresh_frequency(60_000, &hints, Some(i64::MAX), 24 * HOUR_MS, now), i64::MAX - now);
+        let hints = RefreshHints {
+            skip_hours: vec![10],
+            ..Default::default()
+        };
+        assert_eq!(refresh_frequency(60_000, &hints, None, 24 * HOUR_MS, now), 30 * 60_000);
+    }
+}
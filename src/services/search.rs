@@ -0,0 +1,146 @@
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+
+use crate::{
+    db::search::Scored, entities::potential_articles::PotentialArticle,
+    services::content::to_plain_text,
+};
+
+const HIGHLIGHT_OPEN: &str = "<mark>";
+const HIGHLIGHT_CLOSE: &str = "</mark>";
+// amount of chars kept around a match in a snippet
+const SNIPPET_RADIUS: usize = 60;
+const MAX_SNIPPETS: usize = 3;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Highlights {
+    pub title: Option<String>,
+    pub desc: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchResult {
+    pub item: PotentialArticle,
+    pub score: f64,
+    pub highlights: Highlights,
+}
+
+/// terms_regex builds a case insensitive regex matching any of the words of `text`.
+/// Quoted phrases and negated terms of mongo's text search syntax are handled as plain words.
+pub fn terms_regex(text: &str) -> Option<Regex> {
+    let terms: Vec<String> = text
+        .split_whitespace()
+        .map(|t| t.trim_matches(|c: char| c == '"' || c == '-'))
+        .filter(|t| !t.is_empty())
+        .map(regex::escape)
+        .collect();
+    if terms.is_empty() {
+        return None;
+    }
+    RegexBuilder::new(&terms.join("|"))
+        .case_insensitive(true)
+        .build()
+        .ok()
+}
+
+fn mark(text: &str, terms: &Regex) -> String {
+    terms
+        .replace_all(text, format!("{}$0{}", HIGHLIGHT_OPEN, HIGHLIGHT_CLOSE))
+        .to_string()
+}
+
+fn floor_char_boundary(text: &str, mut idx: usize) -> usize {
+    while !text.is_char_boundary(idx) {
+        idx -= 1;
+    }
+    idx
+}
+
+fn ceil_char_boundary(text: &str, mut idx: usize) -> usize {
+    while idx < text.len() && !text.is_char_boundary(idx) {
+        idx += 1;
+    }
+    idx.min(text.len())
+}
+
+/// snippets returns up to `MAX_SNIPPETS` excerpts of `text` surrounding the matches of `terms`,
+/// with matches wrapped in `<mark>` tags. `text` is turned into plain text beforehand.
+pub fn snippets(text: &str, terms: &Regex) -> Vec<String> {
+    let plain = to_plain_text(text);
+    let mut res = vec![];
+    let mut covered_until = 0;
+    for m in terms.find_iter(&plain) {
+        if m.start() < covered_until {
+            continue;
+        }
+        let start = floor_char_boundary(&plain, m.start().saturating_sub(SNIPPET_RADIUS));
+        let end = ceil_char_boundary(&plain, m.end() + SNIPPET_RADIUS);
+        let mut snippet = mark(&plain[start..end], terms);
+        if start > 0 {
+            snippet.insert(0, '…');
+        }
+        if end < plain.len() {
+            snippet.push('…');
+        }
+        res.push(snippet);
+        covered_until = end;
+        if res.len() >= MAX_SNIPPETS {
+            break;
+        }
+    }
+    res
+}
+
+pub fn highlight(hit: Scored<PotentialArticle>, text: &str) -> SearchResult {
+    let highlights = match terms_regex(text) {
+        Some(terms) => Highlights {
+            title: hit
+                .doc
+                .title
+                .as_ref()
+                .filter(|t| terms.is_match(t))
+                .map(|t| mark(t, &terms)),
+            desc: snippets(&hit.doc.desc, &terms),
+        },
+        None => Highlights {
+            title: None,
+            desc: vec![],
+        },
+    };
+    SearchResult {
+        item: hit.doc,
+        score: hit.score,
+        highlights,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snippets() {
+        let terms = terms_regex("Neurotech \"wearables\"").unwrap();
+        assert_eq!(
+            snippets("<p>What is wearable <b>neurotech</b>?</p>", &terms),
+            vec!["What is wearable <mark>neurotech</mark> ?"]
+        );
+        let long = format!("{} wearables {}", "a".repeat(100), "b".repeat(100));
+        assert_eq!(
+            snippets(&long, &terms),
+            vec![format!(
+                "…{} <mark>wearables</mark> {}…",
+                "a".repeat(59),
+                "b".repeat(59)
+            )]
+        );
+        assert!(snippets("nothing to see", &terms).is_empty());
+    }
+
+    #[test]
+    fn test_terms_regex() {
+        assert!(terms_regex("  ").is_none());
+        assert!(terms_regex("-\"\"").is_none());
+        assert!(terms_regex("c++").unwrap().is_match("C++ rocks"));
+    }
+}
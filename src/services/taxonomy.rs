@@ -0,0 +1,136 @@
+use regex::Regex;
+
+use crate::{entities::tag::Tag, error::Error};
+
+/// normalize_category lowercases a category and only keeps its words,
+/// e.g. "Biotech &  Health" becomes "biotech health"
+pub fn normalize_category(category: &str) -> String {
+    category
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// validate_tag checks that a tag has a name and that its rules are valid regexes
+pub fn validate_tag(tag: &Tag) -> Result<(), Error> {
+    if normalize_category(&tag.name).is_empty() {
+        return Error::to_result_string("tag name cannot be empty");
+    }
+    for rule in &tag.rules {
+        Regex::new(rule).map_err(|err| Error(format!("invalid tag rule {}: {}", rule, err)))?;
+    }
+    Ok(())
+}
+
+/// Taxonomy maps the categories of items onto the canonical tags of a tenant
+#[derive(Debug, Clone, Default)]
+pub struct Taxonomy {
+    // normalized alias, tag name
+    aliases: Vec<(String, String)>,
+    rules: Vec<(Regex, String)>,
+}
+
+impl Taxonomy {
+    pub fn new(tags: &[Tag]) -> Self {
+        let mut taxonomy = Taxonomy::default();
+        for tag in tags {
+            tag.aliases
+                .iter()
+                .chain([&tag.name])
+                .chain(tag.label.iter())
+                .map(|alias| normalize_category(alias))
+                .filter(|alias| !alias.is_empty())
+                .for_each(|alias| taxonomy.aliases.push((alias, tag.name.clone())));
+            // invalid rules are refused by the API, those left over are skipped
+            tag.rules
+                .iter()
+                .filter_map(|rule| Regex::new(rule).ok())
+                .for_each(|regex| taxonomy.rules.push((regex, tag.name.clone())));
+        }
+        taxonomy
+    }
+
+    /// resolve returns the tag a category maps to, aliases taking precedence over rules
+    pub fn resolve(&self, category: &str) -> Option<&str> {
+        let normalized = normalize_category(category);
+        self.aliases
+            .iter()
+            .find(|(alias, _)| *alias == normalized)
+            .map(|(_, name)| name.as_str())
+            .or_else(|| {
+                self.rules
+                    .iter()
+                    .find(|(regex, _)| regex.is_match(category))
+                    .map(|(_, name)| name.as_str())
+            })
+    }
+
+    /// tags_of returns the distinct tags of an item, from its `categories` and the `default_tags` of its channel.
+    /// Categories outside of the taxonomy are left out, while default tags are kept as is when unknown.
+    pub fn tags_of(&self, categories: &[String], default_tags: &[String]) -> Vec<String> {
+        let mut tags: Vec<String> = vec![];
+        let mapped = categories
+            .iter()
+            .filter_map(|category| self.resolve(category).map(|t| t.to_string()));
+        let defaults = default_tags
+            .iter()
+            .map(|tag| self.resolve(tag).map(|t| t.to_string()).unwrap_or_else(|| tag.clone()));
+        mapped.chain(defaults).for_each(|tag| {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        });
+        tags
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(name: &str, aliases: &[&str], rules: &[&str]) -> Tag {
+        Tag {
+            tenant: "default".to_string(),
+            name: name.to_string(),
+            label: None,
+            aliases: aliases.iter().map(|a| a.to_string()).collect(),
+            rules: rules.iter().map(|r| r.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_normalize_category() {
+        assert_eq!(normalize_category(" Biotech &  Health "), "biotech health");
+        assert_eq!(normalize_category("neurotech-medtech"), "neurotech medtech");
+        assert_eq!(normalize_category("&"), "");
+    }
+
+    #[test]
+    fn test_taxonomy() {
+        let taxonomy = Taxonomy::new(&[
+            tag("health", &["Biotech & Health", "santé"], &[]),
+            tag("neurotech", &[], &["(?i)neuro"]),
+        ]);
+        assert_eq!(taxonomy.resolve("biotech and health"), None);
+        assert_eq!(taxonomy.resolve("BIOTECH & HEALTH"), Some("health"));
+        assert_eq!(taxonomy.resolve("Santé"), Some("health"));
+        assert_eq!(taxonomy.resolve("Neurostimulation"), Some("neurotech"));
+        let categories: Vec<String> = ["Biotech & Health", "Neurostimulation", "Neurotech", "Funding"]
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        assert_eq!(
+            taxonomy.tags_of(&categories, &["science".to_string(), "Health".to_string()]),
+            vec!["health", "neurotech", "science"]
+        );
+    }
+
+    #[test]
+    fn test_validate_tag() {
+        assert!(validate_tag(&tag("health", &[], &["^health"])).is_ok());
+        assert!(validate_tag(&tag("health", &[], &["("])).is_err());
+        assert!(validate_tag(&tag(" - ", &[], &[])).is_err());
+    }
+}
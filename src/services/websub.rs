@@ -0,0 +1,363 @@
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use sha2::{Sha256, Sha384, Sha512};
+use url::Url;
+use uuid::Uuid;
+
+use crate::{
+    config::WebSubSettings,
+    entities::{
+        channel::Channel,
+        subscription::{Subscription, SubscriptionStatus},
+    },
+    error::Error,
+    services::{
+        content::{attribute, tag_attributes},
+        http::HttpClient,
+    },
+};
+
+/// HubLinks are the WebSub links a feed advertises
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HubLinks {
+    pub hub: Option<String>,
+    // the feed's canonical url, to subscribe to
+    pub topic: Option<String>,
+}
+
+/// hub_links finds the <atom:link rel="hub"> and <atom:link rel="self"> of a feed
+pub fn hub_links(body: &str) -> HubLinks {
+    let mut links = HubLinks::default();
+    for attributes in ["atom:link", "link"]
+        .iter()
+        .flat_map(|tag| tag_attributes(body, tag))
+    {
+        let (Some(rel), Some(href)) = (attribute(&attributes, "rel"), attribute(&attributes, "href")) else {
+            continue;
+        };
+        let href = href.trim().to_string();
+        for rel in rel.split_whitespace() {
+            if rel.eq_ignore_ascii_case("hub") && links.hub.is_none() {
+                links.hub = Some(href.clone());
+            } else if rel.eq_ignore_ascii_case("self") && links.topic.is_none() {
+                links.topic = Some(href.clone());
+            }
+        }
+    }
+    links
+}
+
+/// callback_url is the url a hub calls back for the subscription of a channel, under its `token`
+pub fn callback_url(base: &str, channel_id: i32, token: &str) -> String {
+    format!("{}/websub/{}/{}", base.trim_end_matches('/'), channel_id, token)
+}
+
+/// should_subscribe tells whether a channel must (re)subscribe to `hub` for `topic`.
+/// Hubs which are not https are never subscribed to, since the subscription's secret is sent to them.
+/// Others are when the channel has no subscription for them yet, or one without token, when its lease is about to expire,
+/// when the hub never verified its last request in time, or denied it `retry_denied_after` seconds ago.
+pub fn should_subscribe(
+    existing: Option<&Subscription>,
+    hub: &str,
+    topic: &str,
+    now: i64,
+    settings: &WebSubSettings,
+) -> bool {
+    if !Url::parse(hub).is_ok_and(|hub| hub.scheme() == "https") {
+        return false;
+    }
+    let Some(subscription) =
+        existing.filter(|s| s.hub == hub && s.topic == topic && !s.token.is_empty())
+    else {
+        return true;
+    };
+    match subscription.status {
+        SubscriptionStatus::Active => {
+            let expiring = subscription
+                .expires_at
+                .map(|exp| exp - now < settings.renew_before * 1000)
+                .unwrap_or(true);
+            expiring && now - subscription.requested_at > settings.verify_timeout * 1000
+        }
+        SubscriptionStatus::Requested => now - subscription.requested_at > settings.verify_timeout * 1000,
+        SubscriptionStatus::Denied => now - subscription.requested_at > settings.retry_denied_after * 1000,
+    }
+}
+
+/// accepts_verification tells whether a hub may verify `subscription` at `now`:
+/// only a request still waiting for its verification can be activated.
+pub fn accepts_verification(subscription: &Subscription, now: i64, settings: &WebSubSettings) -> bool {
+    subscription.status == SubscriptionStatus::Requested
+        && now - subscription.requested_at <= settings.verify_timeout.saturating_mul(1000)
+}
+
+/// lease_expiry returns when the lease a hub granted ends, the lease being at most the one asked for
+pub fn lease_expiry(lease_seconds: Option<i64>, now: i64, settings: &WebSubSettings) -> i64 {
+    let lease_seconds = lease_seconds
+        .unwrap_or(settings.lease_seconds)
+        .clamp(0, settings.lease_seconds);
+    now.saturating_add(lease_seconds.saturating_mul(1000))
+}
+
+fn new_secret() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+fn new_token() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+fn hex_decode(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn verify_hmac<M: Mac + hmac::digest::KeyInit>(secret: &str, body: &[u8], expected: &[u8]) -> bool {
+    match <M as hmac::digest::KeyInit>::new_from_slice(secret.as_bytes()) {
+        Ok(mut mac) => {
+            mac.update(body);
+            mac.verify_slice(expected).is_ok()
+        }
+        Err(_) => false,
+    }
+}
+
+/// signature_matches checks the `X-Hub-Signature` of a pushed `body`, e.g. "sha256=<hex digest>"
+pub fn signature_matches(secret: &str, body: &[u8], signature: &str) -> bool {
+    let Some((method, digest)) = signature.trim().split_once('=') else {
+        return false;
+    };
+    let Some(expected) = hex_decode(digest) else {
+        return false;
+    };
+    match method.to_ascii_lowercase().as_str() {
+        "sha1" => verify_hmac::<Hmac<Sha1>>(secret, body, &expected),
+        "sha256" => verify_hmac::<Hmac<Sha256>>(secret, body, &expected),
+        "sha384" => verify_hmac::<Hmac<Sha384>>(secret, body, &expected),
+        "sha512" => verify_hmac::<Hmac<Sha512>>(secret, body, &expected),
+        _ => false,
+    }
+}
+
+/// subscription_request returns the subscription of a channel to `hub` for `topic`, waiting for
+/// the hub's verification of intent. It is recorded before subscribing, since hubs may verify at once.
+/// Renewals keep the secret, the token and the running lease of the `existing` subscription.
+pub fn subscription_request(
+    channel: &Channel,
+    existing: Option<&Subscription>,
+    links: (&str, &str),
+    now: i64,
+) -> Subscription {
+    let (hub, topic) = links;
+    match existing.filter(|s| s.hub == hub && s.topic == topic && !s.token.is_empty()) {
+        Some(renewed) => Subscription {
+            status: SubscriptionStatus::Requested,
+            requested_at: now,
+            reason: None,
+            ..renewed.clone()
+        },
+        None => Subscription {
+            channel_id: channel.id,
+            tenant: channel.tenant.clone(),
+            hub: hub.to_string(),
+            topic: topic.to_string(),
+            secret: new_secret(),
+            token: new_token(),
+            status: SubscriptionStatus::Requested,
+            requested_at: now,
+            expires_at: None,
+            last_push: None,
+            reason: None,
+        },
+    }
+}
+
+/// subscribe asks the hub of `subscription` to push the updates of its topic
+pub async fn subscribe(
+    http: &HttpClient,
+    subscription: &Subscription,
+    settings: &WebSubSettings,
+) -> Result<(), Error> {
+    let callback = callback_url(&settings.callback_url, subscription.channel_id, &subscription.token);
+    let lease_seconds = settings.lease_seconds.to_string();
+    http.post_form(
+        &subscription.hub,
+        &[
+            ("hub.mode", "subscribe"),
+            ("hub.topic", &subscription.topic),
+            ("hub.callback", &callback),
+            ("hub.secret", &subscription.secret),
+            ("hub.lease_seconds", &lease_seconds),
+        ],
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::source_type::SourceType;
+
+    fn settings() -> WebSubSettings {
+        WebSubSettings {
+            enabled: true,
+            callback_url: "https://patishie.example.com/patishie/".to_string(),
+            lease_seconds: 864_000,
+            renew_before: 86_400,
+            verify_timeout: 3_600,
+            retry_denied_after: 86_400,
+            poll_interval: 21_600_000,
+        }
+    }
+
+    #[test]
+    fn test_hub_links() {
+        let feed = r#"<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom"><channel>
+            <link>https://example.com</link>
+            <atom:link rel="hub" href="https://pubsubhubbub.appspot.com/" />
+            <atom:link rel="self" type="application/rss+xml" href="https://example.com/feed" />
+            </channel></rss>"#;
+        assert_eq!(
+            hub_links(feed),
+            HubLinks {
+                hub: Some("https://pubsubhubbub.appspot.com/".to_string()),
+                topic: Some("https://example.com/feed".to_string()),
+            }
+        );
+        let atom = r#"<feed xmlns="http://www.w3.org/2005/Atom">
+            <link rel="self hub" href="https://example.com/atom" /></feed>"#;
+        assert_eq!(hub_links(atom).topic.as_deref(), Some("https://example.com/atom"));
+        assert_eq!(hub_links("<rss><channel></channel></rss>"), HubLinks::default());
+    }
+
+    #[test]
+    fn test_callback_url() {
+        assert_eq!(
+            callback_url(&settings().callback_url, 12, "abc"),
+            "https://patishie.example.com/patishie/websub/12/abc"
+        );
+    }
+
+    #[test]
+    fn test_should_subscribe() {
+        let settings = settings();
+        let subscription = Subscription {
+            channel_id: 1,
+            tenant: "default".to_string(),
+            hub: "https://hub.example.com".to_string(),
+            topic: "https://example.com/feed".to_string(),
+            secret: "s".to_string(),
+            token: "t".to_string(),
+            status: SubscriptionStatus::Active,
+            requested_at: 0,
+            expires_at: Some(864_000_000),
+            last_push: None,
+            reason: None,
+        };
+        let (hub, topic) = (subscription.hub.as_str(), subscription.topic.as_str());
+        assert!(should_subscribe(None, hub, topic, 0, &settings));
+        assert!(!should_subscribe(Some(&subscription), hub, topic, 1000, &settings));
+        // renewed within a day of its expiry, unless a renewal is waiting for verification
+        assert!(should_subscribe(Some(&subscription), hub, topic, 800_000_000, &settings));
+        let renewing = Subscription {
+            requested_at: 799_000_000,
+            ..subscription.clone()
+        };
+        assert!(!should_subscribe(Some(&renewing), hub, topic, 800_000_000, &settings));
+        assert!(should_subscribe(Some(&subscription), "https://other.hub", topic, 1000, &settings));
+        let requested = Subscription {
+            status: SubscriptionStatus::Requested,
+            expires_at: None,
+            ..subscription.clone()
+        };
+        assert!(!should_subscribe(Some(&requested), hub, topic, 1000, &settings));
+        assert!(should_subscribe(Some(&requested), hub, topic, 3_600_001, &settings));
+        let denied = Subscription {
+            status: SubscriptionStatus::Denied,
+            requested_at: 900_000_000,
+            expires_at: None,
+            ..subscription.clone()
+        };
+        assert!(!should_subscribe(Some(&denied), hub, topic, 900_001_000, &settings));
+        assert!(should_subscribe(Some(&denied), hub, topic, 986_400_001, &settings));
+        // subscriptions made before callbacks had a token
+        let legacy = Subscription {
+            token: String::new(),
+            ..subscription.clone()
+        };
+        assert!(should_subscribe(Some(&legacy), hub, topic, 1000, &settings));
+        // hub.secret is only sent over https
+        assert!(!should_subscribe(None, "http://hub.example.com", topic, 0, &settings));
+        assert!(!should_subscribe(None, "not a hub", topic, 0, &settings));
+    }
+
+    #[test]
+    fn test_subscription_request() {
+        let channel = Channel::new("default", "news", "https://example.com", SourceType::RSSFeed);
+        let links = ("https://hub.example.com", "https://example.com/feed");
+        let requested = subscription_request(&channel, None, links, 1000);
+        assert_eq!(requested.status, SubscriptionStatus::Requested);
+        assert_eq!(requested.token.len(), 32);
+        let active = Subscription {
+            status: SubscriptionStatus::Active,
+            expires_at: Some(864_001_000),
+            ..requested.clone()
+        };
+        let renewed = subscription_request(&channel, Some(&active), links, 800_000_000);
+        assert_eq!(
+            renewed,
+            Subscription {
+                status: SubscriptionStatus::Requested,
+                requested_at: 800_000_000,
+                ..active.clone()
+            }
+        );
+        assert!(renewed.is_active(800_000_000));
+        let moved = subscription_request(&channel, Some(&active), ("https://other.hub", links.1), 1000);
+        assert_ne!(moved.token, active.token);
+    }
+
+    #[test]
+    fn test_accepts_verification() {
+        let settings = settings();
+        let channel = Channel::new("default", "news", "https://example.com", SourceType::RSSFeed);
+        let links = ("https://hub.example.com", "https://example.com/feed");
+        let requested = subscription_request(&channel, None, links, 0);
+        assert!(accepts_verification(&requested, 3_600_000, &settings));
+        assert!(!accepts_verification(&requested, 3_600_001, &settings));
+        for status in [SubscriptionStatus::Active, SubscriptionStatus::Denied] {
+            let answered = Subscription {
+                status,
+                ..requested.clone()
+            };
+            assert!(!accepts_verification(&answered, 1000, &settings));
+        }
+    }
+
+    #[test]
+    fn test_lease_expiry() {
+        let settings = settings();
+        assert_eq!(lease_expiry(Some(3_600), 1000, &settings), 3_601_000);
+        assert_eq!(lease_expiry(None, 0, &settings), 864_000_000);
+        assert_eq!(lease_expiry(Some(i64::MAX), 0, &settings), 864_000_000);
+        assert_eq!(lease_expiry(Some(-5), 1000, &settings), 1000);
+    }
+
+    #[test]
+    fn test_signature_matches() {
+        // HMAC-SHA256 of "hello" with the key "key"
+        let signature = "sha256=9307b3b915efb5171ff14d8cb55fbcc798c6c0ef1456d66ded1a6aa723a58b7b";
+        assert!(signature_matches("key", b"hello", signature));
+        assert!(!signature_matches("key", b"hello!", signature));
+        assert!(!signature_matches("other", b"hello", signature));
+        // HMAC-SHA1 of "hello" with the key "key"
+        assert!(signature_matches("key", b"hello", "sha1=b34ceac4516ff23a143e61d79d0fa7a4fbe5f266"));
+        assert!(!signature_matches("key", b"hello", "md5=00"));
+        assert!(!signature_matches("key", b"hello", "sha256=zz"));
+    }
+}
@@ -1,27 +1,290 @@
 use std::sync::Arc;
 
 use chrono::Utc;
+use mongodb::bson::doc;
 use tokio::{spawn, task::JoinHandle};
 use uuid::Uuid;
 
 use crate::{
-    config::Settings,
-    entities::{channel::Channel, source_type::SourceType},
+    config::{Settings, WebSubSettings},
+    entities::{
+        bakery_response::ScrapeMetadata, channel::Channel, credential::CredentialAuth,
+        detected_encoding::DetectedEncoding, potential_articles::PotentialArticle, redirect::UrlChange,
+        refresh_hints::RefreshHints,
+        refresh_report::RefreshReport, source_type::SourceType, subscription::Subscription,
+    },
     error::{self, Error},
     find_index,
-    services::{bakery::get_cookies_from_bakery, panya::process_data, rss::get_cookies_from_rss},
+    db::model::CollectionModel,
+    services::{
+        bakery::BakeryError,
+        credentials::channel_credential,
+        http::HttpError,
+        redirect::{may_move, observe_redirect, RedirectOutcome},
+        schedule::refresh_frequency,
+        panya::process_data,
+        rss::get_cookies_from_rss,
+        language::declared_language, taxonomy::Taxonomy,
+        websub::{should_subscribe, subscribe, subscription_request, HubLinks},
+    },
+    utils::Clients,
     DBBag,
 };
 
+#[derive(Debug, Default)]
+pub struct Fetched {
+    pub articles: Vec<PotentialArticle>,
+    pub scrape: Option<ScrapeMetadata>,
+    pub warnings: Vec<String>,
+    // whether the channel's url answered, i.e. whether `moved_to` was observed
+    pub reached: bool,
+    // url the channel's url permanently redirects to
+    pub moved_to: Option<String>,
+    // the channel's url answered 410 Gone
+    pub gone: bool,
+    // the publisher's refresh hints, none when unknown
+    pub hints: Option<RefreshHints>,
+    // the WebSub hub of the channel's feed
+    pub hub: Option<HubLinks>,
+    // the encoding the channel's feed was transcoded from
+    pub encoding: Option<DetectedEncoding>,
+}
+
+impl Fetched {
+    /// failure tells why nothing was fetched: no article came, along with warnings
+    pub fn failure(&self) -> Option<String> {
+        (self.articles.is_empty() && !self.warnings.is_empty()).then(|| self.warnings.join("; "))
+    }
+}
+
+/// fetch_articles parses the articles of a channel from its bakery or rss source.
+/// Bakery being unavailable is an error, while other failures of bakery give no article and a warning.
+/// `credential` is the channel's resolved credential, see `services::credentials::channel_credential`.
+pub async fn fetch_articles(
+    clients: &Clients,
+    channel: &Channel,
+    credential: Option<&CredentialAuth>,
+    log_id: Uuid,
+) -> Result<Fetched, Error> {
+    Ok(match channel.source_type {
+        SourceType::RSSFeed => match get_cookies_from_rss(&clients.http, channel, credential, log_id).await {
+            Ok(feed) => Fetched {
+                articles: feed.articles,
+                reached: true,
+                moved_to: feed.moved_to,
+                hints: Some(feed.hints),
+                hub: Some(feed.hub),
+                warnings: feed
+                    .encoding
+                    .iter()
+                    .filter(|encoding| encoding.lossy)
+                    .map(|encoding| format!("invalid {} sequences were replaced", encoding.name))
+                    .chain(feed.parse_error)
+                    .collect(),
+                encoding: feed.encoding,
+                ..Default::default()
+            },
+            // other hints are kept until the feed answers again
+            Err(HttpError::RetryAfter(err, delay)) => Fetched {
+                warnings: vec![err],
+                hints: Some(RefreshHints {
+                    retry_after: Some(delay),
+                    ..channel.refresh_hints.clone().unwrap_or_default()
+                }),
+                ..Default::default()
+            },
+            Err(HttpError::Gone(url)) => Fetched {
+                warnings: vec![format!("{} answered 410 Gone, channel disabled", url)],
+                gone: true,
+                ..Default::default()
+            },
+            Err(HttpError::Failed(err)) => Fetched {
+                warnings: vec![err],
+                ..Default::default()
+            },
+        },
+        SourceType::Bakery => match clients
+            .bakery
+            .get_cookies(channel.id, &channel.url, credential, log_id)
+            .await
+        {
+            Ok(response) => Fetched {
+                articles: response.articles,
+                warnings: response.metadata.warnings.clone(),
+                scrape: Some(response.metadata),
+                ..Default::default()
+            },
+            Err(err @ BakeryError::Failed(_)) => Fetched {
+                warnings: vec![Error::from(err).0],
+                ..Default::default()
+            },
+            Err(err) => return Err(err.into()),
+        },
+        SourceType::Other => {
+            return Err(Error("SourceType::Other not implemeented yet".to_string()));
+        }
+    })
+}
+
+/// follow_url_changes disables a channel which url is gone, and moves it to where its url
+/// permanently redirects once seen `confirmations` times in a row. Returns a note of the move, if any,
+/// or of why it was not made.
+async fn follow_url_changes(
+    db_bag: &DBBag,
+    channel: &Channel,
+    fetched: &Fetched,
+    confirmations: u32,
+) -> Result<Option<String>, Error> {
+    let now = Utc::now().timestamp_millis();
+    let channels_coll = &db_bag.channels_coll;
+    if fetched.gone {
+        channels_coll.disable(channel.id, now).await?;
+        return Ok(None);
+    }
+    if !fetched.reached {
+        return Ok(None);
+    }
+    let moved_to = fetched.moved_to.as_deref().filter(|target| *target != channel.url);
+    let movable = moved_to
+        .map(|target| may_move(&channel.url, target, channel.has_secrets()))
+        .unwrap_or(true);
+    match observe_redirect(channel.pending_redirect.as_ref(), moved_to, now, confirmations, movable) {
+        RedirectOutcome::Move(target) => {
+            let previous = UrlChange {
+                url: channel.url.clone(),
+                date: now,
+            };
+            channels_coll.move_url(channel.id, &target, &previous).await?;
+            Ok(Some(format!("moved from {} to {}", channel.url, target)))
+        }
+        RedirectOutcome::Pending(pending) => {
+            if pending.as_ref() != channel.pending_redirect.as_ref() {
+                channels_coll.set_pending_redirect(channel.id, pending.as_ref()).await?;
+            }
+            Ok(None)
+        }
+        RedirectOutcome::Held(pending) => {
+            channels_coll.set_pending_redirect(channel.id, Some(&pending)).await?;
+            Ok(Some(format!(
+                "not moved to {}, which would get the channel's headers or credential from another host or over http",
+                pending.target
+            )))
+        }
+    }
+}
+
+/// schedule_channel derives the next refresh frequency of a channel from the hints of its publisher.
+/// Channels `pushed` by a WebSub hub are polled at most every `websub.poll_interval`.
+async fn schedule_channel(
+    db_bag: &DBBag,
+    channel: &Channel,
+    hints: &RefreshHints,
+    pushed: bool,
+    settings: &Settings,
+) -> Result<(), Error> {
+    let base = channel.base_refresh_frequency.unwrap_or(channel.refresh_frequency);
+    let floor = match pushed {
+        true => (base as i64).max(settings.websub.poll_interval),
+        false => base as i64,
+    };
+    let frequency = refresh_frequency(
+        floor,
+        hints,
+        channel.min_refresh_interval,
+        settings.max_hinted_refresh_interval,
+        Utc::now().timestamp_millis(),
+    )
+    .min(i32::MAX as i64) as i32;
+    if frequency == channel.refresh_frequency && channel.refresh_hints.as_ref() == Some(hints) {
+        return Ok(());
+    }
+    db_bag.channels_coll.set_schedule(channel.id, frequency, base, hints).await
+}
+
+/// follow_hub subscribes a channel to the WebSub hub its feed advertises, or renews its lease,
+/// and returns the channel's subscription
+async fn follow_hub(
+    db_bag: &DBBag,
+    clients: &Clients,
+    channel: &Channel,
+    links: Option<&HubLinks>,
+    settings: &WebSubSettings,
+) -> Result<Option<Subscription>, Error> {
+    let existing = db_bag.subscriptions_coll.get(channel.id).await?;
+    if !settings.enabled || settings.callback_url.is_empty() {
+        return Ok(existing);
+    }
+    let Some((hub, links)) = links.and_then(|links| Some((links.hub.as_deref()?, links))) else {
+        return Ok(existing);
+    };
+    let topic = links.topic.as_deref().unwrap_or(&channel.url);
+    let now = Utc::now().timestamp_millis();
+    if !should_subscribe(existing.as_ref(), hub, topic, now, settings) {
+        return Ok(existing);
+    }
+    let subscription = subscription_request(channel, existing.as_ref(), (hub, topic), now);
+    db_bag.subscriptions_coll.upsert(&subscription).await?;
+    subscribe(&clients.http, &subscription, settings).await?;
+    Ok(Some(subscription))
+}
+
+/// ingest_articles stores the new articles of a channel, whether fetched or pushed by a hub,
+/// and returns the report of their processing
+pub async fn ingest_articles(
+    articles: &[PotentialArticle],
+    db_bag: &DBBag,
+    clients: &Clients,
+    channel: &Channel,
+    settings: &Settings,
+    log_id: Uuid,
+) -> RefreshReport {
+    if let Some(language) = declared_language(articles) {
+        if channel.language.as_ref() != Some(&language) {
+            if let Err(err) = db_bag.channels_coll.set_language(channel.id, &language).await {
+                println!("[ERR ] {:?}", err);
+            }
+        }
+    }
+    let tags = db_bag
+        .tags_coll
+        .find(doc! {"tenant": &channel.tenant}, None, None)
+        .await
+        .unwrap_or_default();
+    let res = process_data(
+        articles,
+        db_bag,
+        channel,
+        &Taxonomy::new(&tags),
+        &clients.http,
+        settings,
+        log_id,
+    )
+    .await;
+    res.unwrap_or_else(|err| {
+        println!("[ERR ] {:?}", err);
+        RefreshReport {
+            date: Utc::now().timestamp_millis(),
+            fetched: articles.len() as i64,
+            warnings: vec![err.0],
+            ..Default::default()
+        }
+    })
+}
+
 async fn update_channel(
     db_bag: Arc<DBBag>,
     settings: Arc<Settings>,
-    channel_id: i32,
-    channel_name: String,
-    channel_url: String,
+    clients: Arc<Clients>,
+    channel: Channel,
     log_id: Uuid,
-    source_type: SourceType,
 ) -> Result<i64, Error> {
+    let channel_id = channel.id;
+    let channel_name = channel.name.clone();
+    let source_type = channel.source_type.clone();
+    // bakery channels are paused, rather than failed, while bakery is down
+    if source_type == SourceType::Bakery && !clients.bakery.is_available() {
+        return Err(BakeryError::CircuitOpen.into());
+    }
     // now time
     let _ = db_bag
         .channels_coll
@@ -34,20 +297,37 @@ async fn update_channel(
             ))
         })?;
     // parse result from bakery or rss source
-    let parsed_result = match source_type {
-        SourceType::RSSFeed => get_cookies_from_rss(&channel_url, channel_id, log_id)
-            .await
-            .unwrap_or_default(),
-        SourceType::Bakery => get_cookies_from_bakery(&settings.api_path, &channel_url, log_id)
-            .await
-            .unwrap_or_default(),
-        SourceType::Other => {
-            return Err(Error("SourceType::Other not implemeented yet".to_string()));
+    let credential = channel_credential(&db_bag, &channel).await?;
+    let fetched = fetch_articles(&clients, &channel, credential.as_ref(), log_id).await?;
+    let moved = follow_url_changes(&db_bag, &channel, &fetched, settings.redirect_confirmations)
+        .await
+        .unwrap_or_else(|err| {
+            println!("[ERR ] {:?}", err);
+            None
+        });
+    let subscription = follow_hub(&db_bag, &clients, &channel, fetched.hub.as_ref(), &settings.websub)
+        .await
+        .unwrap_or_else(|err| {
+            println!("[ERR ] {:?}", err);
+            None
+        });
+    if let Some(hints) = &fetched.hints {
+        let pushed = subscription
+            .map(|s| s.is_active(Utc::now().timestamp_millis()))
+            .unwrap_or(false);
+        if let Err(err) = schedule_channel(&db_bag, &channel, hints, pushed, &settings).await {
+            println!("[ERR ] {:?}", err);
         }
+    }
+    let parsed_result = fetched.articles;
+    let mut report = RefreshReport {
+        date: Utc::now().timestamp_millis(),
+        ..Default::default()
     };
     let mut success = true;
     if parsed_result.is_empty() {
         success = false;
+        report.warnings.push("no articles found".to_string());
         println!(
             "[{}] ({}) source_type: {}, channel_name: {}, channel_id: {} - no articles found",
             log_id,
@@ -57,18 +337,14 @@ async fn update_channel(
             channel_id
         );
     } else {
-        let res = process_data(
-            &parsed_result,
-            &db_bag.items_coll,
-            &db_bag.channels_coll,
-            &channel_name,
-            &channel_url,
-            source_type,
-        )
-        .await;
-        if res.is_err() {
-            println!("[ERR ] {:?}", res.err());
-        }
+        report = ingest_articles(&parsed_result, &db_bag, &clients, &channel, &settings, log_id).await;
+    }
+    report.warnings.extend(fetched.warnings);
+    report.warnings.extend(moved);
+    report.scrape = fetched.scrape;
+    report.encoding = fetched.encoding;
+    if let Err(err) = db_bag.channels_coll.set_refresh_report(channel_id, &report).await {
+        println!("[ERR ] {:?}", err);
     }
     db_bag
         .channels_coll
@@ -82,20 +358,23 @@ async fn update_channel(
         })
 }
 
+/// spawn_tasks refreshes `channels`, except those of the `ledger`, still in flight.
+/// Returns the refresh task of each channel, by id.
 pub fn spawn_tasks(
-    channels: &Vec<Channel>,
+    channels: &[Channel],
     settings: &Arc<Settings>,
     db_bag: &Arc<DBBag>,
+    clients: &Arc<Clients>,
     ledger: &mut Vec<i32>,
-) -> Vec<JoinHandle<Result<i64, Error>>> {
+) -> Vec<(i32, JoinHandle<Result<i64, Error>>)> {
     let mut tasks = vec![];
     for c in channels {
-        let channel_name = c.name.clone();
+        let channel = c.clone();
         let channel_url = c.url.clone();
         let channel_id = c.id;
-        let db_bag_clone = Arc::clone(&db_bag);
-        let settings_clone = Arc::clone(&settings);
-        let source_type = c.source_type.clone();
+        let db_bag_clone = Arc::clone(db_bag);
+        let settings_clone = Arc::clone(settings);
+        let clients_clone = Arc::clone(clients);
         if ledger.contains(&channel_id) {
             continue;
         }
@@ -103,7 +382,7 @@ pub fn spawn_tasks(
             ledger.remove(v);
         }
         // ledger
-        tasks.push(spawn(async move {
+        tasks.push((channel_id, spawn(async move {
             let before = Utc::now();
             let task_id = Uuid::new_v4();
             eprintln!(
@@ -112,16 +391,7 @@ pub fn spawn_tasks(
                 before.timestamp_millis(),
                 &channel_url
             );
-            let res = update_channel(
-                db_bag_clone,
-                settings_clone,
-                channel_id,
-                channel_name.clone(),
-                channel_url.clone(),
-                task_id,
-                source_type,
-            )
-            .await;
+            let res = update_channel(db_bag_clone, settings_clone, clients_clone, channel, task_id).await;
             let after = Utc::now();
             eprintln!(
                 "[{}] ({}) Done for {}, in {}ms",
@@ -131,8 +401,40 @@ pub fn spawn_tasks(
                 after.timestamp_millis() - before.timestamp_millis()
             );
             res
-        }));
+        })));
     }
 
     tasks
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fetched_failure() {
+        let unreachable = Fetched {
+            warnings: vec!["https://example.com/feed: connection refused".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(unreachable.failure().as_deref(), Some("https://example.com/feed: connection refused"));
+        let broken = Fetched {
+            reached: true,
+            warnings: vec!["https://example.com/feed is not a valid feed".to_string()],
+            ..Default::default()
+        };
+        assert!(broken.failure().is_some());
+        let lossy = Fetched {
+            articles: vec![PotentialArticle::default()],
+            reached: true,
+            warnings: vec!["invalid Shift_JIS sequences were replaced".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(lossy.failure(), None);
+        let empty = Fetched {
+            reached: true,
+            ..Default::default()
+        };
+        assert_eq!(empty.failure(), None);
+    }
+}
@@ -8,27 +8,89 @@ use std::{
 use url::{Position, Url};
 
 use crate::{
-    db::{channel::Channels, items::Items, mongo::Handle},
-    entities::{channel::Channel, potential_articles::PotentialArticle},
+    config::Settings,
+    db::{
+        channel::Channels, entities::AppliedMigration, items::Items, migrations::Migrations,
+        mongo::Handle, secrets::Secrets, subscriptions::Subscriptions, tags::Tags,
+    },
+    entities::{
+        channel::Channel, credential::Credential, potential_articles::PotentialArticle,
+        subscription::Subscription, tag::Tag,
+    },
     error::Error,
+    services::{bakery::BakeryClient, http::HttpClient},
 };
 
+/// Timestamps lower than this value are considered to be expressed in seconds
+/// (in ms, it would be early March 1973).
+pub const SECONDS_TIMESTAMP_CEILING: i64 = 100_000_000_000;
+
 pub struct DBBag {
     // db_handle: Arc<Handle>,
     pub channels_coll: Channels<Channel>,
     pub items_coll: Items<PotentialArticle>,
+    pub migrations_coll: Migrations<AppliedMigration>,
+    pub tags_coll: Tags<Tag>,
+    pub secrets_coll: Secrets<Credential>,
+    pub subscriptions_coll: Subscriptions<Subscription>,
 }
 
 impl DBBag {
-    pub fn new(db_handle: Arc<Handle>) -> Result<Self, Error> {
+    pub fn new(db_handle: Arc<Handle>, settings: &Settings) -> Result<Self, Error> {
+        let db_name = &settings.database;
+        let collections = &settings.collections;
         Ok(Self {
             // db_handle: db_handle.clone(),
-            channels_coll: Channels::<Channel>::new(db_handle.clone(), "panya")?,
-            items_coll: Items::<PotentialArticle>::new(db_handle.clone(), "panya")?,
+            channels_coll: Channels::<Channel>::new(
+                db_handle.clone(),
+                db_name,
+                &collections.channels,
+            )?,
+            items_coll: Items::<PotentialArticle>::new(
+                db_handle.clone(),
+                db_name,
+                &collections.items,
+            )?,
+            migrations_coll: Migrations::<AppliedMigration>::new(
+                db_handle.clone(),
+                db_name,
+                &collections.migrations,
+            )?,
+            tags_coll: Tags::<Tag>::new(db_handle.clone(), db_name, &collections.tags)?,
+            secrets_coll: Secrets::<Credential>::new(db_handle.clone(), db_name, &collections.secrets)?,
+            subscriptions_coll: Subscriptions::<Subscription>::new(
+                db_handle.clone(),
+                db_name,
+                &collections.subscriptions,
+            )?,
+        })
+    }
+}
+
+/// Clients holds the http clients shared by every channel
+pub struct Clients {
+    pub http: HttpClient,
+    pub bakery: BakeryClient,
+}
+
+impl Clients {
+    pub fn new(settings: &Settings) -> Result<Self, Error> {
+        Ok(Self {
+            http: HttpClient::new(&settings.http)?,
+            bakery: BakeryClient::new(settings)?,
         })
     }
 }
 
+/// to_timestamp_ms converts a timestamp in seconds to milliseconds.
+/// Timestamps already in milliseconds are returned as is.
+pub fn to_timestamp_ms(timestamp: i64) -> i64 {
+    if timestamp > 0 && timestamp < SECONDS_TIMESTAMP_CEILING {
+        return timestamp * 1000;
+    }
+    timestamp
+}
+
 pub fn now_timestamp_ms() -> u128 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -85,7 +147,7 @@ impl Add<u64> for Millisecond {
     type Output = u64;
 
     fn add(self, rhs: u64) -> Self::Output {
-        self.clone().0 + rhs
+        self.0 + rhs
     }
 }
 
@@ -138,7 +200,7 @@ impl Add<u64> for Second {
     type Output = u64;
 
     fn add(self, rhs: u64) -> Self::Output {
-        self.clone().0 + rhs
+        self.0 + rhs
     }
 }
 
@@ -171,6 +233,13 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_to_timestamp_ms() {
+        assert_eq!(to_timestamp_ms(1696769957), 1696769957000);
+        assert_eq!(to_timestamp_ms(1696769957000), 1696769957000);
+        assert_eq!(to_timestamp_ms(0), 0);
+    }
+
     #[test]
     fn test_i_can_clean_url() {
         let trial = "https://www3.nhk.or.jp/news/easy/?limit=5";